@@ -0,0 +1,155 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use js_sys::{ Array, Float32Array, Object, Reflect, Uint32Array, Uint8Array };
+use crate::host::Atlas;
+use crate::chunk::create_chunk;
+use crate::mesher::{ Meshed, Mesher };
+use crate::utils as U;
+use crate::world::World;
+
+// every chunk of a region decoded and meshed in one go, packed into flat buffers so a worker
+// can transfer them instead of copying; chunks run k, j, i like make_region's queue
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct Baked {
+    // chunks per axis
+    pub n: i32,
+    pub tol: Option<u8>,
+    // face quads culled inside the region, with dir per instance
+    pub quad: bool,
+    // CHUNK³ bytes per chunk
+    pub vox: Vec<u8>,
+    // 4 bytes per voxel, only with tol
+    pub rgba: Vec<u8>,
+    pub pos: Vec<f32>,
+    pub scl: Vec<f32>,
+    // empty unless tol
+    pub col: Vec<f32>,
+    // empty unless quad
+    pub dir: Vec<f32>,
+    // instances per chunk, to split pos/scl/col back up
+    pub cnt: Vec<u32>,
+}
+
+// one chunk's share of a Baked: chunk id, vox, rgba, mesh
+pub type Part = (i32, Vec<u8>, Option<Vec<u8>>, Meshed);
+
+pub fn bake<A: Atlas + ?Sized>(atlas: &A, world: &World, mesher: Mesher) -> Baked {
+    let n = world.chunks();
+    let tol = match mesher {
+        Mesher::Color(t) => Some(t),
+        _ => None,
+    };
+    let quad = mesher == Mesher::Quad;
+    let mut out = Baked { n, tol, quad, ..Default::default() };
+    let mut chunks = Vec::new();
+    for k in 0..n {
+        for j in 0..n {
+            for i in 0..n {
+                let c = create_chunk(i, j, k);
+                if quad {
+                    c.decode(atlas, world, mesher);
+                } else {
+                    c.fill_with(atlas, world, mesher);
+                }
+                chunks.push(c);
+            }
+        }
+    }
+    // quads are culled against the rest of the region here, so the page only redoes its border
+    let s = U::CHUNK;
+    let solid = |x: i32, y: i32, z: i32| {
+        if x < 0 || y < 0 || z < 0 || x >= n * s || y >= n * s || z >= n * s {
+            return false;
+        }
+        chunks[((z / s) * n * n + (y / s) * n + x / s) as usize].voxel(x % s, y % s, z % s) == Some(1)
+    };
+    for c in chunks.iter() {
+        let Some((vox, rgba, mut m)) = c.parts() else {
+            continue;
+        };
+        if quad {
+            let (ox, oy, oz) = (c.x as i32, c.y as i32, c.z as i32);
+            m = c.quads(|x, y, z| solid(ox + x, oy + y, oz + z)).unwrap_or_default();
+        }
+        out.vox.extend_from_slice(&vox);
+        if let Some(rgba) = rgba {
+            out.rgba.extend_from_slice(&rgba);
+        }
+        out.pos.extend_from_slice(&m.pos);
+        out.scl.extend_from_slice(&m.scl);
+        out.col.extend_from_slice(&m.col);
+        out.dir.extend_from_slice(&m.dir);
+        out.cnt.push(m.cnt);
+    }
+    out
+}
+
+impl Baked {
+    pub fn split(&self) -> Vec<Part> {
+        let size = (U::CHUNK * U::CHUNK * U::CHUNK) as usize;
+        let mut out = Vec::with_capacity(self.cnt.len());
+        let n = self.n.max(1) as usize;
+        let mut at = 0;
+        for (p, cnt) in self.cnt.iter().copied().enumerate() {
+            let (i, j, k) = (p % n, (p / n) % n, p / (n * n));
+            let Some(vox) = self.vox.get(p * size..(p + 1) * size) else {
+                break;
+            };
+            let rgba = self.tol.and_then(|_| self.rgba.get(p * size * 4..(p + 1) * size * 4)).map(|b| b.to_vec());
+            let end = at + (cnt as usize) * 3;
+            let m = Meshed {
+                pos: self.pos.get(at..end).unwrap_or_default().to_vec(),
+                scl: self.scl.get(at..end).unwrap_or_default().to_vec(),
+                col: self.col.get(at..end).unwrap_or_default().to_vec(),
+                dir: self.dir.get(at / 3..end / 3).unwrap_or_default().to_vec(),
+                cnt,
+            };
+            at = end;
+            out.push((U::chunk_id(i as i32, j as i32, k as i32), vox.to_vec(), rgba, m));
+        }
+        out
+    }
+    // { n, tol, quad, vox, rgba, pos, scl, col, dir, cnt } plus the buffers to list as transferables
+    pub fn to_js(&self) -> (Object, Array) {
+        let o = Object::new();
+        let list = Array::new();
+        let _ = Reflect::set(&o, &"n".into(), &JsValue::from_f64(self.n as f64));
+        if let Some(t) = self.tol {
+            let _ = Reflect::set(&o, &"tol".into(), &JsValue::from_f64(t as f64));
+        }
+        let _ = Reflect::set(&o, &"quad".into(), &JsValue::from_bool(self.quad));
+        let bytes = [("vox", &self.vox), ("rgba", &self.rgba)];
+        for (k, v) in bytes {
+            let a = Uint8Array::from(v.as_slice());
+            list.push(&a.buffer());
+            let _ = Reflect::set(&o, &k.into(), &a);
+        }
+        let floats = [("pos", &self.pos), ("scl", &self.scl), ("col", &self.col), ("dir", &self.dir)];
+        for (k, v) in floats {
+            let a = Float32Array::from(v.as_slice());
+            list.push(&a.buffer());
+            let _ = Reflect::set(&o, &k.into(), &a);
+        }
+        let cnt = Uint32Array::from(self.cnt.as_slice());
+        list.push(&cnt.buffer());
+        let _ = Reflect::set(&o, &"cnt".into(), &cnt);
+        (o, list)
+    }
+    pub fn from_js(o: &JsValue) -> Option<Baked> {
+        let get = |k: &str| Reflect::get(o, &k.into()).ok();
+        let bytes = |k: &str| get(k)?.dyn_into::<Uint8Array>().ok().map(|a| a.to_vec());
+        let floats = |k: &str| get(k)?.dyn_into::<Float32Array>().ok().map(|a| a.to_vec());
+        Some(Baked {
+            n: get("n")?.as_f64()? as i32,
+            tol: get("tol").and_then(|v| v.as_f64()).map(|t| t as u8),
+            quad: get("quad").is_some_and(|v| v.is_truthy()),
+            vox: bytes("vox")?,
+            rgba: bytes("rgba").unwrap_or_default(),
+            pos: floats("pos")?,
+            scl: floats("scl")?,
+            col: floats("col").unwrap_or_default(),
+            dir: floats("dir").unwrap_or_default(),
+            cnt: get("cnt")?.dyn_into::<Uint32Array>().ok()?.to_vec(),
+        })
+    }
+}
@@ -0,0 +1,102 @@
+use wasm_bindgen::prelude::*;
+use js_sys::{ Object, Reflect };
+
+// what one region holds on to, in bytes
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct Usage {
+    // occupancy plus colour, when kept
+    pub voxels: usize,
+    // instance buffers of every meshed chunk
+    pub meshes: usize,
+    // the decoded atlas image
+    pub image: usize,
+    // chunk bookkeeping, paid even before anything is loaded
+    pub other: usize,
+}
+
+impl Usage {
+    pub fn total(&self) -> usize {
+        self.voxels + self.meshes + self.image + self.other
+    }
+    pub fn add(&mut self, u: &Usage) {
+        self.voxels += u.voxels;
+        self.meshes += u.meshes;
+        self.image += u.image;
+        self.other += u.other;
+    }
+}
+
+// eviction starts once regions hold more than `bytes` or there are more than `regions` of
+// them; inactive regions go in order of distance (in regions) plus `age` per frame since
+// they were last wanted. The default only caps the count at 32 regions, as before byte
+// budgets existed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Budget {
+    pub bytes: usize,
+    pub regions: usize,
+    pub age: f32,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget { bytes: usize::MAX, regions: 32, age: 1.0 / 60.0 }
+    }
+}
+
+impl Budget {
+    pub fn score(&self, dist: f32, idle: u64) -> f32 {
+        dist + self.age * idle as f32
+    }
+    pub fn over(&self, regions: usize, bytes: usize) -> bool {
+        regions > self.regions || bytes > self.bytes
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct Stats {
+    pub regions: usize,
+    pub usage: Usage,
+    pub budget: usize,
+    // totals since the Regions was created
+    pub evicted: usize,
+    pub freed: usize,
+}
+
+impl Stats {
+    pub fn to_js(&self) -> Object {
+        let o = Object::new();
+        let u = &self.usage;
+        let fields = [
+            ("regions", self.regions),
+            ("bytes", u.total()),
+            ("voxels", u.voxels),
+            ("meshes", u.meshes),
+            ("image", u.image),
+            ("other", u.other),
+            ("budget", self.budget),
+            ("evicted", self.evicted),
+            ("freed", self.freed),
+        ];
+        for (k, v) in fields {
+            let v = if v == usize::MAX { f64::INFINITY } else { v as f64 };
+            let _ = Reflect::set(&o, &k.into(), &JsValue::from_f64(v));
+        }
+        o
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_caps_regions_not_bytes() {
+        let b = Budget::default();
+        assert!(!b.over(32, usize::MAX - 1));
+        assert!(b.over(33, 0));
+        let b = Budget { bytes: 100, regions: usize::MAX, age: 0.5 };
+        assert!(!b.over(1000, 100) && b.over(1, 101));
+        // ten frames unseen weigh as much as five regions of distance
+        assert_eq!(b.score(1.0, 10), b.score(6.0, 0));
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// byte storage that survives page loads (IndexedDB, Cache API) or not (MemoryStore);
+// answers come through callbacks because browser storage is asynchronous
+pub trait Store {
+    fn get(&self, key: &str, done: Box<dyn FnOnce(Option<Vec<u8>>)>);
+    fn put(&self, key: &str, bytes: &[u8]);
+    fn remove(&self, key: &str);
+    fn keys(&self, done: Box<dyn FnOnce(Vec<String>)>);
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    map: RefCell<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn len(&self) -> usize {
+        self.map.borrow().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.map.borrow().is_empty()
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &str, done: Box<dyn FnOnce(Option<Vec<u8>>)>) {
+        let v = self.map.borrow().get(key).cloned();
+        done(v);
+    }
+    fn put(&self, key: &str, bytes: &[u8]) {
+        self.map.borrow_mut().insert(key.to_string(), bytes.to_vec());
+    }
+    fn remove(&self, key: &str) {
+        self.map.borrow_mut().remove(key);
+    }
+    fn keys(&self, done: Box<dyn FnOnce(Vec<String>)>) {
+        let keys = self.map.borrow().keys().cloned().collect();
+        done(keys);
+    }
+}
+
+// encoded region atlases (the png/webp file as downloaded) keyed by dataset version, region
+// and the tile's manifest hash, so neither a new version nor a re-cut tile reads an old file
+#[derive(Clone)]
+pub struct Cache {
+    store: Rc<dyn Store>,
+    version: String,
+}
+
+impl Cache {
+    pub fn new(store: Rc<dyn Store>, version: &str) -> Cache {
+        Cache { store, version: version.to_string() }
+    }
+    // same store, another dataset version
+    pub fn with_version(&self, version: &str) -> Cache {
+        Cache::new(self.store.clone(), version)
+    }
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+    // hash is empty without a manifest entry
+    pub fn key(&self, z: i32, i: i32, j: i32, hash: &str) -> String {
+        match hash {
+            "" => format!("{}/{}_{}_{}", self.version, z, i, j),
+            h => format!("{}/{}_{}_{}_{}", self.version, z, i, j, h),
+        }
+    }
+    pub fn load(&self, z: i32, i: i32, j: i32, hash: &str, done: Box<dyn FnOnce(Option<Vec<u8>>)>) {
+        self.store.get(&self.key(z, i, j, hash), done);
+    }
+    pub fn save(&self, z: i32, i: i32, j: i32, hash: &str, bytes: &[u8]) {
+        self.store.put(&self.key(z, i, j, hash), bytes);
+    }
+    // drops everything stored under another version
+    pub fn purge(&self) {
+        let (store, prefix) = (self.store.clone(), format!("{}/", self.version));
+        self.store.keys(
+            Box::new(move |keys| {
+                for k in keys.iter().filter(|k| !k.starts_with(&prefix)) {
+                    store.remove(k);
+                }
+            })
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(c: &Cache, z: i32, i: i32, j: i32, hash: &str) -> Option<Vec<u8>> {
+        let out = Rc::new(RefCell::new(None));
+        let o = out.clone();
+        c.load(z, i, j, hash, Box::new(move |b| *o.borrow_mut() = b));
+        out.take()
+    }
+
+    #[test]
+    fn purge_drops_other_versions() {
+        let store = Rc::new(MemoryStore::default());
+        let old = Cache::new(store.clone(), "v1");
+        old.save(17, 3, 4, "", &[1, 2, 3]);
+        let new = Cache::new(store.clone(), "v2");
+        new.save(17, 3, 4, "", &[9]);
+        assert_eq!(read(&old, 17, 3, 4, ""), Some(vec![1, 2, 3]));
+        new.purge();
+        assert_eq!(store.len(), 1);
+        assert_eq!(read(&old, 17, 3, 4, ""), None);
+        assert_eq!(read(&new, 17, 3, 4, ""), Some(vec![9]));
+        assert_eq!(read(&new, 17, 3, 5, ""), None);
+    }
+
+    #[test]
+    fn recut_tile_misses_within_a_version() {
+        let c = Cache::new(Rc::new(MemoryStore::default()), "v1");
+        c.save(17, 3, 4, "aa", &[1]);
+        assert_eq!(read(&c, 17, 3, 4, "aa"), Some(vec![1]));
+        assert_eq!(read(&c, 17, 3, 4, "bb"), None);
+        assert_eq!(read(&c, 17, 3, 4, ""), None);
+    }
+}
@@ -2,6 +2,160 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use js_sys::{ Array, Function };
 use crate::utils as U;
+use crate::web as W;
+use crate::ray::raycast;
+use crate::tour::{ Key, Tour };
+use crate::world::World;
+
+// ids match the old numeric modes so `mode(i32)` callers keep working
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    Scroll = -1,
+    Fly = 0,
+    Walk = 1,
+    Frozen = 2,
+    Follow = 3,
+    Orbit = 4,
+    Map = 5,
+    // playing back a recorded tour
+    Tour = 6,
+}
+
+impl Mode {
+    pub fn from_id(x: i32) -> Mode {
+        match x {
+            -1 => Mode::Scroll,
+            0 => Mode::Fly,
+            1 => Mode::Walk,
+            3 => Mode::Follow,
+            4 => Mode::Orbit,
+            5 => Mode::Map,
+            6 => Mode::Tour,
+            _ => Mode::Frozen,
+        }
+    }
+    pub fn from_name(s: &str) -> Option<Mode> {
+        Some(match s {
+            "scroll" => Mode::Scroll,
+            "fly" | "creative" => Mode::Fly,
+            "walk" | "survive" => Mode::Walk,
+            "frozen" => Mode::Frozen,
+            "follow" => Mode::Follow,
+            "orbit" => Mode::Orbit,
+            "map" => Mode::Map,
+            "tour" => Mode::Tour,
+            _ => return None,
+        })
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Scroll => "scroll",
+            Mode::Fly => "fly",
+            Mode::Walk => "walk",
+            Mode::Frozen => "frozen",
+            Mode::Follow => "follow",
+            Mode::Orbit => "orbit",
+            Mode::Map => "map",
+            Mode::Tour => "tour",
+        }
+    }
+    // modes that move the player body with gravity and collision
+    pub fn physical(self) -> bool {
+        self == Mode::Walk || self == Mode::Follow
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Projection {
+    Perspective,
+    // orthographic box, handy for the map view and isometric shots
+    Ortho,
+    // reversed-Z with an infinite far plane; the host must clear depth to 0 and test GEQUAL
+    Reversed,
+}
+
+impl Projection {
+    pub fn from_name(s: &str) -> Option<Projection> {
+        Some(match s {
+            "perspective" => Projection::Perspective,
+            "ortho" | "orthographic" => Projection::Ortho,
+            "reversed" | "infinite" => Projection::Reversed,
+            _ => return None,
+        })
+    }
+}
+
+// lens and movement settings; fov is vertical, in degrees
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Params {
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+    pub projection: Projection,
+    // half the visible height of the ortho box; 0 sizes it to the perspective view at the target
+    pub height: f32,
+    pub move_speed: f32,
+    pub jump: f32,
+    pub grav: f32,
+    // rates (1/s) at which velocity closes on the input while keys are held / released;
+    // infinite (the default) or 0 is instant, so smoothing is opt-in
+    pub accel: f32,
+    pub friction: f32,
+    // share of accel/friction a walking player keeps while off the ground
+    pub air: f32,
+    // radians per pixel of mouse movement
+    pub sensitivity: f32,
+    // seconds for smoothed mouse turns to catch up; 0 turns immediately
+    pub smooth: f32,
+    // analog stick deadzone and full-deflection turn rate (rad/s)
+    pub deadzone: f32,
+    pub turn_rate: f32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            fov: 28.0,
+            near: 0.1,
+            far: 4000.0,
+            projection: Projection::Perspective,
+            height: 0.0,
+            move_speed: 12.0,
+            jump: 12.0,
+            grav: -50.0,
+            accel: f32::INFINITY,
+            friction: f32::INFINITY,
+            air: 0.2,
+            sensitivity: 1.0 / 250.0,
+            smooth: 0.0,
+            deadzone: 0.15,
+            turn_rate: 2.5,
+        }
+    }
+}
+
+impl Params {
+    // keys missing from opts keep their current value
+    fn read(&mut self, opts: &JsValue) {
+        self.fov = W::get_f32(opts, "fov", self.fov);
+        self.near = W::get_f32(opts, "near", self.near);
+        self.far = W::get_f32(opts, "far", self.far);
+        self.height = W::get_f32(opts, "height", self.height);
+        self.move_speed = W::get_f32(opts, "speed", self.move_speed);
+        self.jump = W::get_f32(opts, "jump", self.jump);
+        self.grav = W::get_f32(opts, "gravity", self.grav);
+        self.accel = W::get_f32(opts, "accel", self.accel);
+        self.friction = W::get_f32(opts, "friction", self.friction);
+        self.air = W::get_f32(opts, "air", self.air);
+        self.sensitivity = W::get_f32(opts, "sensitivity", self.sensitivity);
+        self.smooth = W::get_f32(opts, "smooth", self.smooth);
+        self.deadzone = W::get_f32(opts, "deadzone", self.deadzone);
+        self.turn_rate = W::get_f32(opts, "turnRate", self.turn_rate);
+        if let Some(p) = W::get_str(opts, "projection").and_then(|s| Projection::from_name(&s)) {
+            self.projection = p;
+        }
+    }
+}
 
 #[wasm_bindgen]
 pub struct Camera {
@@ -10,20 +164,32 @@ pub struct Camera {
     mvp: [f32; 16],
     yaw: f32,
     pitch: f32,
-    mode: i32,
+    mode: Mode,
     dir: [f32; 3],
     vel: [f32; 3],
     dash: f32,
-    turn: f32,
-    move_speed: f32,
-    jump: f32,
-    grav: f32,
+    // mouse turn (yaw, pitch radians) not yet applied when smoothing
+    pending: [f32; 2],
+    // right-stick deflection after the deadzone
+    spin: [f32; 2],
+    params: Params,
     ground: f32,
     size: [f32; 3],
     x0: f32,
     y0: f32,
     is_ground: bool,
     scroll: f32,
+    boom: f32,
+    boom_len: f32,
+    target: [f32; 3],
+    dist: f32,
+    zoom: f32,
+    tour: Tour,
+    // seconds between recorded keys while recording
+    rec: Option<f32>,
+    clock: f32,
+    looped: bool,
+    world: World,
 }
 
 fn face_dir(yaw: f32, pitch: f32) -> [f32; 3] {
@@ -31,7 +197,7 @@ fn face_dir(yaw: f32, pitch: f32) -> [f32; 3] {
     let mut t = [0.0f32; 16];
     U::look_at(&mut r, [0.0,0.0,0.0], [0.0,0.0,-1.0], [0.0,1.0,0.0]);
     // reuse identity pattern via perspective with aspect=1 to avoid new helper; then overwrite to identity
-    for i in 0..16 { r[i] = 0.0; }
+    r.fill(0.0);
     r[0]=1.0; r[5]=1.0; r[10]=1.0; r[15]=1.0;
     U::rotate_y(&mut t, &r, yaw);
     U::rotate_x(&mut r, &t, pitch);
@@ -40,12 +206,26 @@ fn face_dir(yaw: f32, pitch: f32) -> [f32; 3] {
     U::transform_vec3(&mut out, &f, &r);
     out
 }
+// rescales a stick axis so motion starts from 0 at the edge of the deadzone
+fn dead(v: f32, zone: f32) -> f32 {
+    let a = v.abs();
+    if a <= zone || zone >= 1.0 {
+        return 0.0;
+    }
+    v.signum() * ((a - zone) / (1.0 - zone)).min(1.0)
+}
+// exponential approach, so the result doesn't depend on how dt is sliced
+fn approach(v: f32, to: f32, rate: f32, dt: f32) -> f32 {
+    if rate <= 0.0 || rate == f32::INFINITY {
+        return to;
+    }
+    to + (v - to) * (-rate * dt).exp()
+}
 fn look_target(pos: [f32; 3], face: [f32; 3]) -> [f32; 3] {
     [pos[0] + face[0] * 10.0, pos[1] + face[1] * 10.0, pos[2] + face[2] * 10.0]
 }
-fn clamp_to_face(pos: f32, half: f32, sign: f32, base: i32) -> f32 {
-    if sign > 0.0 { pos.min((base as f32) + 1.0 - half) } else { pos.max((base as f32) + half) }
-}
+// gap kept between the player box and a voxel face so the next sweep doesn't start inside it
+const SKIN: f32 = 1e-3;
 fn move_dir(face: [f32; 3], dir: [f32; 3], speed: f32, planar: bool) -> [f32; 3] {
     let f = face;
     let up = [0.0, 1.0, 0.0];
@@ -72,37 +252,57 @@ fn move_dir(face: [f32; 3], dir: [f32; 3], speed: f32, planar: bool) -> [f32; 3]
 
 #[wasm_bindgen(js_name = createCamera)]
 pub fn create_camera(opts: &JsValue) -> Camera {
-    let yaw = std::f32::consts::PI * 0.5;
-    let pitch = -std::f32::consts::PI * 0.45;
-    let mode = -1;
-    let x = U::get_f32(opts, "X", 0.0);
-    let y = U::get_f32(opts, "Y", 0.0);
-    let z = U::get_f32(opts, "Z", 0.0);
-    let pos = [x, y, z];
-    let face = face_dir(yaw, pitch);
-    let eye = look_target(pos, face);
-    let mvp = [0.0; 16];
-    let size = [0.8, 1.8, 0.8];
-    Camera {
-        pos,
-        eye,
-        mvp,
-        yaw,
-        pitch,
-        mode,
-        dir: [0.0, 0.0, 0.0],
-        vel: [0.0, 0.0, 0.0],
-        dash: 1.0,
-        turn: 1.0 / 250.0,
-        move_speed: 12.0,
-        jump: 12.0,
-        grav: -50.0,
-        ground: 0.0,
-        size,
-        x0: x,
-        y0: y,
-        is_ground: false,
-        scroll: 0.0,
+    let x = W::get_f32(opts, "X", 0.0);
+    let y = W::get_f32(opts, "Y", 0.0);
+    let z = W::get_f32(opts, "Z", 0.0);
+    let mut cam = Camera::new(x, y, z);
+    if let Ok(w) = js_sys::Reflect::get(opts, &"world".into()) {
+        cam.set_world(World::from_js(&w));
+    }
+    cam.configure(opts);
+    cam
+}
+
+impl Camera {
+    pub fn new(x: f32, y: f32, z: f32) -> Camera {
+        let yaw = std::f32::consts::PI * 0.5;
+        let pitch = -std::f32::consts::PI * 0.45;
+        let mode = Mode::Scroll;
+        let pos = [x, y, z];
+        let face = face_dir(yaw, pitch);
+        let eye = look_target(pos, face);
+        let mvp = [0.0; 16];
+        let size = [0.8, 1.8, 0.8];
+        Camera {
+            pos,
+            eye,
+            mvp,
+            yaw,
+            pitch,
+            mode,
+            dir: [0.0, 0.0, 0.0],
+            vel: [0.0, 0.0, 0.0],
+            dash: 1.0,
+            pending: [0.0, 0.0],
+            spin: [0.0, 0.0],
+            params: Params::default(),
+            ground: 0.0,
+            size,
+            x0: x,
+            y0: y,
+            is_ground: false,
+            scroll: 0.0,
+            boom: 6.0,
+            boom_len: 6.0,
+            target: pos,
+            dist: 40.0,
+            zoom: 400.0,
+            tour: Tour::default(),
+            rec: None,
+            clock: 0.0,
+            looped: false,
+            world: World::default(),
+        }
     }
 }
 
@@ -116,6 +316,12 @@ impl Camera {
     pub fn mvp(&self) -> js_sys::Float32Array {
         js_sys::Float32Array::from(&self.mvp[..])
     }
+    // origin xyz then direction xyz of the ray under a point in clip space (-1..1, y up)
+    #[wasm_bindgen(js_name = ray)]
+    pub fn ray_of(&self, x: f32, y: f32) -> js_sys::Float32Array {
+        let (o, d) = self.ray(x, y);
+        js_sys::Float32Array::from(&[o[0], o[1], o[2], d[0], d[1], d[2]][..])
+    }
     pub fn asdw(&mut self, axis: i32, delta: f32) {
         if axis == 0 {
             self.dir[1] = delta;
@@ -127,27 +333,96 @@ impl Camera {
         }
         if axis == 2 {
             self.dir[0] = delta;
-            return;
         }
     }
+    // fly: descend, walk/follow: dash, orbit: lower the target, map: zoom out
     pub fn shift(&mut self, is_press: bool) {
-        if self.mode == 0 {
-            self.asdw(0, if is_press { -1.0 } else { 0.0 });
-            return;
-        }
-        if self.mode == 1 {
+        if self.mode.physical() {
             self.dash = if is_press { 3.0 } else { 1.0 };
+            return;
         }
+        self.asdw(0, if is_press { -1.0 } else { 0.0 });
     }
+    // fly: ascend, walk/follow: jump, orbit: raise the target, map: zoom in
     pub fn space(&mut self, is_press: bool) {
-        if self.mode == 0 {
-            self.asdw(0, if is_press { 1.0 } else { 0.0 });
+        if self.mode.physical() {
+            if self.is_ground && is_press { self.vel[1] = self.params.jump; }
             return;
         }
-        if self.mode == 1 && self.is_ground && is_press { self.vel[1] = self.jump; }
+        self.asdw(0, if is_press { 1.0 } else { 0.0 });
     }
     pub fn mode(&mut self, x: i32) {
-        self.mode = x;
+        self.set_mode(Mode::from_id(x));
+    }
+    #[wasm_bindgen(js_name = setMode)]
+    pub fn set_mode_of(&mut self, name: &str) -> bool {
+        let Some(m) = Mode::from_name(name) else {
+            return false;
+        };
+        self.set_mode(m);
+        true
+    }
+    #[wasm_bindgen(getter, js_name = modeName)]
+    pub fn mode_name(&self) -> String {
+        self.current_mode().name().to_string()
+    }
+    // orbit around a world point, e.g. a picked building
+    pub fn orbit(&mut self, x: f32, y: f32, z: f32) {
+        self.set_mode(Mode::Orbit);
+        self.target = [x, y, z];
+        self.pos = self.target;
+    }
+    // samples pos/yaw/pitch every `interval` seconds of step time until stop()
+    pub fn record(&mut self, interval: f32) {
+        self.tour = Tour::default();
+        self.clock = 0.0;
+        self.rec = Some(interval.max(0.0));
+    }
+    pub fn stop(&mut self) {
+        if self.rec.take().is_some() {
+            let k = self.key();
+            self.tour.push(k);
+        }
+    }
+    pub fn play(&mut self, looped: bool) -> bool {
+        if self.tour.keys.len() < 2 {
+            return false;
+        }
+        self.stop();
+        self.set_mode(Mode::Tour);
+        self.clock = 0.0;
+        self.looped = looped;
+        true
+    }
+    #[wasm_bindgen(getter, js_name = isRecording)]
+    pub fn is_recording(&self) -> bool {
+        self.rec.is_some()
+    }
+    // {"keys":[{"t","pos":[x,y,z],"yaw","pitch"}, ...]}
+    #[wasm_bindgen(js_name = exportTour)]
+    pub fn export_tour(&self) -> String {
+        serde_wasm_bindgen::to_value(&self.tour)
+            .ok()
+            .and_then(|v| js_sys::JSON::stringify(&v).ok())
+            .and_then(|s| s.as_string())
+            .unwrap_or_default()
+    }
+    #[wasm_bindgen(js_name = importTour)]
+    pub fn import_tour(&mut self, json: &str) -> bool {
+        let Ok(v) = js_sys::JSON::parse(json) else {
+            return false;
+        };
+        let Ok(t) = serde_wasm_bindgen::from_value::<Tour>(v) else {
+            return false;
+        };
+        self.set_tour(t)
+    }
+    // gamepad sticks in Gamepad API orientation (y down): left moves, right turns
+    pub fn axes(&mut self, mx: f32, my: f32, lx: f32, ly: f32) {
+        let z = self.params.deadzone;
+        self.dir[0] = dead(mx, z);
+        self.dir[2] = -dead(my, z);
+        self.spin = [dead(lx, z), dead(ly, z)];
     }
     pub fn turn(&mut self, delta: &JsValue) {
         let (dx, dy) = if let Some(arr) = delta.dyn_ref::<Array>() {
@@ -158,55 +433,264 @@ impl Camera {
         } else {
             (delta.as_f64().unwrap_or(0.0) as f32, 0.0)
         };
-        let r = if self.mode == 1 { 1.0 } else { 0.1 };
-        self.yaw += dx * r * self.turn;
-        self.pitch += dy * r * self.turn;
+        self.look(dx, dy);
+    }
+    // fov, near, far, projection ("perspective" | "ortho" | "reversed"), height, speed, jump,
+    // gravity, yaw and pitch; anything left out keeps its current value
+    pub fn configure(&mut self, opts: &JsValue) {
+        let mut p = self.params;
+        p.read(opts);
+        self.set_params(p);
+        let yaw = W::get_f32(opts, "yaw", self.yaw);
+        let pitch = W::get_f32(opts, "pitch", self.pitch);
+        self.set_angles(yaw, pitch);
+    }
+    #[wasm_bindgen(getter, js_name = isReversed)]
+    pub fn is_reversed(&self) -> bool {
+        self.params.projection == Projection::Reversed
+    }
+    pub fn update(&mut self, aspect: f32) {
+        self.project(aspect);
+    }
+    pub fn tick(&mut self, dt: f32, pick: &JsValue) {
+        let pf: Function = pick.clone().unchecked_into();
+        self.step(dt, &|x: f32, y: f32, z: f32| {
+            pf.call3(
+                &JsValue::NULL,
+                &JsValue::from_f64(x as f64),
+                &JsValue::from_f64(y as f64),
+                &JsValue::from_f64(z as f64)
+            )
+                .unwrap_or(JsValue::from_f64(0.0))
+                .as_f64()
+                .unwrap_or(0.0) as i32
+        });
+    }
+}
+
+impl Camera {
+    pub fn position(&self) -> [f32; 3] {
+        self.pos
+    }
+    pub fn matrix(&self) -> &[f32; 16] {
+        &self.mvp
+    }
+    pub fn size(&self) -> [f32; 3] {
+        self.size
+    }
+    pub fn params(&self) -> Params {
+        self.params
+    }
+    pub fn set_params(&mut self, p: Params) {
+        self.params = p;
+    }
+    pub fn set_angles(&mut self, yaw: f32, pitch: f32) {
         let half = std::f32::consts::FRAC_PI_2 - 0.01;
-        self.pitch = self.pitch.min(half).max(-half);
+        self.yaw = yaw;
+        self.pitch = pitch.min(half).max(-half);
+        self.eye = look_target(self.pos, face_dir(self.yaw, self.pitch));
+    }
+    // only the autoscroll wrap depends on it
+    pub fn set_world(&mut self, w: World) {
+        self.world = w;
+    }
+    pub fn tour(&self) -> &Tour {
+        &self.tour
+    }
+    // re-pushes the keys so out-of-order ones are dropped and yaw is unwrapped
+    pub fn set_tour(&mut self, t: Tour) -> bool {
+        let mut tour = Tour::default();
+        let base = t.keys.first().map(|k| k.t).unwrap_or(0.0);
+        for k in t.keys {
+            tour.push(Key { t: k.t - base, ..k });
+        }
+        self.tour = tour;
+        self.rec = None;
+        !self.tour.keys.is_empty()
+    }
+    fn key(&self) -> Key {
+        Key { t: self.clock, pos: self.pos, yaw: self.yaw, pitch: self.pitch }
+    }
+    pub fn current_mode(&self) -> Mode {
+        self.mode
+    }
+    pub fn set_mode(&mut self, m: Mode) {
+        if m == Mode::Orbit && self.mode != Mode::Orbit {
+            let f = face_dir(self.yaw, self.pitch);
+            let head = self.head();
+            self.target = [head[0] + f[0] * self.dist, head[1] + f[1] * self.dist, head[2] + f[2] * self.dist];
+        }
+        if m == Mode::Orbit {
+            self.pos = self.target;
+        }
+        self.dir = [0.0, 0.0, 0.0];
+        self.mode = m;
+    }
+    fn head(&self) -> [f32; 3] {
+        [self.pos[0], self.pos[1] + self.size[1] * 0.5, self.pos[2]]
+    }
+    // eye, target and up of the view for the current mode
+    fn view(&self) -> ([f32; 3], [f32; 3], [f32; 3]) {
+        let up = [0.0, 1.0, 0.0];
         let f = face_dir(self.yaw, self.pitch);
-        self.eye = look_target(self.pos, f);
+        let head = self.head();
+        match self.mode {
+            Mode::Follow => {
+                let l = self.boom_len;
+                ([head[0] - f[0] * l, head[1] - f[1] * l, head[2] - f[2] * l], head, up)
+            }
+            Mode::Orbit => {
+                let (t, d) = (self.target, self.dist);
+                ([t[0] - f[0] * d, t[1] - f[1] * d, t[2] - f[2] * d], t, up)
+            }
+            Mode::Map => ([self.pos[0], self.pos[1] + self.zoom, self.pos[2]], self.pos, [0.0, 0.0, -1.0]),
+            _ => (head, [self.eye[0], head[1] + self.eye[1] - self.pos[1], self.eye[2]], up),
+        }
     }
-    pub fn update(&mut self, aspect: f32) {
+    // unprojects the near plane and a deeper one; before the first project() it falls back to the view axis
+    pub fn ray(&self, x: f32, y: f32) -> ([f32; 3], [f32; 3]) {
+        let mut inv = [0.0; 16];
+        if !U::invert(&mut inv, &self.mvp) {
+            let (eye, at, _) = self.view();
+            return (eye, [at[0] - eye[0], at[1] - eye[1], at[2] - eye[2]]);
+        }
+        let mut near = [0.0; 3];
+        let mut far = [0.0; 3];
+        // reversed-Z puts near at 1 and infinity at 0, so aim at half depth (twice the near distance)
+        let (zn, zf) = if self.params.projection == Projection::Reversed { (1.0, 0.5) } else { (-1.0, 1.0) };
+        U::transform_vec3(&mut near, &[x, y, zn], &inv);
+        U::transform_vec3(&mut far, &[x, y, zf], &inv);
+        (near, [far[0] - near[0], far[1] - near[1], far[2] - near[2]])
+    }
+    // map mode drags the view, every other mode turns it
+    pub fn look(&mut self, dx: f32, dy: f32) {
+        if self.mode == Mode::Map {
+            self.pos[0] -= dx * self.zoom * 0.002;
+            self.pos[2] -= dy * self.zoom * 0.002;
+            return;
+        }
+        let r = if self.mode == Mode::Fly || self.mode == Mode::Scroll { 0.1 } else { 1.0 };
+        let k = r * self.params.sensitivity;
+        if self.params.smooth > 0.0 {
+            self.pending[0] += dx * k;
+            self.pending[1] += dy * k;
+            return;
+        }
+        self.set_angles(self.yaw + dx * k, self.pitch + dy * k);
+    }
+    // drains smoothed mouse turns and applies the right stick
+    fn steer(&mut self, dt: f32) {
+        let mut d = [0.0; 2];
+        if self.pending != [0.0, 0.0] {
+            let f = 1.0 - (-dt / self.params.smooth.max(1e-4)).exp();
+            d = [self.pending[0] * f, self.pending[1] * f];
+            self.pending = [self.pending[0] - d[0], self.pending[1] - d[1]];
+        }
+        if self.spin == [0.0, 0.0] && d == [0.0, 0.0] {
+            return;
+        }
+        if self.mode == Mode::Map {
+            let pan = self.zoom * 0.5 * dt;
+            self.pos[0] += self.spin[0] * pan;
+            self.pos[2] += self.spin[1] * pan;
+            return;
+        }
+        let rate = self.params.turn_rate * dt;
+        self.set_angles(self.yaw + d[0] + self.spin[0] * rate, self.pitch + d[1] + self.spin[1] * rate);
+    }
+    pub fn project(&mut self, aspect: f32) {
         let mut p = [0.0; 16];
         let mut v = [0.0; 16];
-        U::perspective(&mut p, (28.0_f32).to_radians(), aspect, 0.1, 4000.0);
-        let off = self.size[1] * 0.5;
-        U::look_at(
-            &mut v,
-            [self.pos[0], self.pos[1] + off, self.pos[2]],
-            [self.eye[0], self.eye[1] + off, self.eye[2]],
-            [0.0, 1.0, 0.0]
-        );
+        let o = self.params;
+        let fovy = o.fov.to_radians();
+        let (eye, at, up) = self.view();
+        match o.projection {
+            Projection::Perspective => U::perspective(&mut p, fovy, aspect, o.near, o.far),
+            Projection::Reversed => U::perspective_reversed(&mut p, fovy, aspect, o.near),
+            Projection::Ortho => {
+                let h = if o.height > 0.0 {
+                    o.height
+                } else {
+                    let d = [at[0] - eye[0], at[1] - eye[1], at[2] - eye[2]];
+                    (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt() * (0.5 * fovy).tan()
+                };
+                let w = h * aspect;
+                U::ortho(&mut p, -w, w, -h, h, o.near, o.far);
+            }
+        }
+        U::look_at(&mut v, eye, at, up);
         U::mul(&mut self.mvp, &p, &v)
     }
-    pub fn tick(&mut self, dt: f32, pick: &JsValue) {
-        if self.mode == 2 {
+    pub fn step<P: Fn(f32, f32, f32) -> i32>(&mut self, dt: f32, pick: &P) {
+        if self.mode == Mode::Tour {
+            self.clock += dt;
+            let end = self.tour.duration();
+            if self.clock > end {
+                self.clock = if self.looped && end > 0.0 { self.clock % end } else { end };
+            }
+            if let Some(k) = self.tour.sample(self.clock) {
+                self.pos = k.pos;
+                self.set_angles(k.yaw, k.pitch);
+            }
+            return;
+        }
+        if let Some(every) = self.rec {
+            let due = self.tour.keys.last().is_none_or(|k| self.clock - k.t >= every);
+            if due {
+                let k = self.key();
+                self.tour.push(k);
+            }
+            self.clock += dt;
+        }
+        self.steer(dt);
+        if self.mode == Mode::Frozen {
+            return;
+        }
+        if self.mode == Mode::Orbit {
+            self.yaw += self.dir[0] * dt * 1.5;
+            self.dist = (self.dist * (1.0 - self.dir[2] * dt)).clamp(2.0, 2000.0);
+            self.target[1] += self.dir[1] * dt * self.params.move_speed;
+            self.pos = self.target;
+            return;
+        }
+        if self.mode == Mode::Map {
+            let pan = self.zoom * 0.5 * self.dash;
+            self.pos[0] += self.dir[0] * dt * pan;
+            self.pos[2] -= self.dir[2] * dt * pan;
+            self.zoom = (self.zoom * (1.0 - self.dir[1] * dt)).clamp(20.0, 8000.0);
             return;
         }
-        if self.mode == -1 {
-            self.scroll -= dt * self.move_speed;
+        if self.mode == Mode::Scroll {
+            self.scroll -= dt * self.params.move_speed;
             self.pos[0] = self.x0 + self.scroll;
-            if self.pos[0] < 0.0 { self.pos[0] = (U::ROW * U::REGION) as f32; }
-            if self.pos[0] > (U::ROW * U::REGION) as f32 { self.pos[0] = 0.0; }
+            let (lo, hi) = (self.world.origin[0], self.world.origin[0] + self.world.span());
+            if self.pos[0] < lo { self.pos[0] = hi; }
+            if self.pos[0] > hi { self.pos[0] = lo; }
             let f = face_dir(self.yaw, self.pitch);
             self.eye = look_target(self.pos, f);
             return;
         }
-        let speed = self.move_speed * self.dash * (if self.mode == 0 { 20.0 } else { 1.0 });
+        let speed = self.params.move_speed * self.dash * (if self.mode == Mode::Fly { 20.0 } else { 1.0 });
         let f = face_dir(self.yaw, self.pitch);
-        let m = move_dir(f, self.dir, speed, self.mode == 1);
-        self.vel[0] = m[0];
-        self.vel[2] = m[2];
-        if self.mode == 0 {
+        let m = move_dir(f, self.dir, speed, self.mode.physical());
+        let held = self.dir != [0.0, 0.0, 0.0];
+        let mut rate = if held { self.params.accel } else { self.params.friction };
+        if self.mode.physical() && !self.is_ground {
+            rate *= self.params.air;
+        }
+        self.vel[0] = approach(self.vel[0], m[0], rate, dt);
+        self.vel[2] = approach(self.vel[2], m[2], rate, dt);
+        if self.mode == Mode::Fly {
+            self.vel[1] = approach(self.vel[1], self.dir[1] * speed, rate, dt);
             self.pos[0] += self.vel[0] * dt;
-            self.pos[1] += self.dir[1] * dt * speed;
+            self.pos[1] += self.vel[1] * dt;
             self.pos[2] += self.vel[2] * dt;
             let f = face_dir(self.yaw, self.pitch);
             self.eye = look_target(self.pos, f);
             return;
         }
-        if self.mode == 1 {
-            self.vel[1] += self.grav * dt;
+        if self.mode.physical() {
+            self.vel[1] += self.params.grav * dt;
             let vmax = self.vel[0].abs().max(self.vel[1].abs().max(self.vel[2].abs()));
             let mut steps = ((vmax * dt) / 0.25).ceil() as i32;
             if steps < 1 {
@@ -214,14 +698,10 @@ impl Camera {
             }
             let sdt = dt / (steps as f32);
             self.is_ground = false;
-            let pf: Function = pick.clone().unchecked_into();
             for _ in 0..steps {
-                self.pos[1] += self.vel[1] * sdt;
-                self.collide(1, &pf);
-                self.pos[0] += self.vel[0] * sdt;
-                self.collide(0, &pf);
-                self.pos[2] += self.vel[2] * sdt;
-                self.collide(2, &pf);
+                self.collide(1, self.vel[1] * sdt, pick);
+                self.collide(0, self.vel[0] * sdt, pick);
+                self.collide(2, self.vel[2] * sdt, pick);
             }
             if self.pos[1] < self.ground {
                 self.pos[1] = self.y0 / 4.0;
@@ -229,39 +709,159 @@ impl Camera {
             }
             let f = face_dir(self.yaw, self.pitch);
             self.eye = look_target(self.pos, f);
+            if self.mode == Mode::Follow {
+                // pull the boom in so walls between the player and the camera don't hide them
+                let back = [-f[0], -f[1], -f[2]];
+                let hit = raycast(self.head(), back, self.boom, |x, y, z| (pick(x as f32, y as f32, z as f32) != 0) as u8);
+                self.boom_len = hit.map(|h| (h.dist - 0.2).max(0.3)).unwrap_or(self.boom);
+            }
         }
     }
-}
-
-impl Camera {
-    fn collide(&mut self, axis: i32, pick: &Function) {
-        let v = self.vel[axis as usize];
-        if v == 0.0 {
+    // any solid voxel overlapping the player box centred at p
+    fn blocked<P: Fn(f32, f32, f32) -> i32>(&self, p: [f32; 3], pick: &P) -> bool {
+        let lo: Vec<i32> = (0..3).map(|a| (p[a] - self.size[a] * 0.5 + SKIN).floor() as i32).collect();
+        let hi: Vec<i32> = (0..3).map(|a| (p[a] + self.size[a] * 0.5 - SKIN).floor() as i32).collect();
+        for x in lo[0]..=hi[0] {
+            for y in lo[1]..=hi[1] {
+                for z in lo[2]..=hi[2] {
+                    if pick(x as f32, y as f32, z as f32) != 0 {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+    // moves the box d along one axis, stopping at the first voxel layer it would enter;
+    // a blocked horizontal move on the ground retries one block higher (step-up)
+    fn collide<P: Fn(f32, f32, f32) -> i32>(&mut self, axis: usize, d: f32, pick: &P) {
+        if d == 0.0 {
             return;
         }
-        let s = v.signum();
-        let mut xyz = self.pos;
-        xyz[axis as usize] += s;
-        let floor_xyz = [xyz[0].floor(), xyz[1].floor(), xyz[2].floor()];
-        let hit = pick
-            .call3(
-                &JsValue::NULL,
-                &JsValue::from_f64(floor_xyz[0] as f64),
-                &JsValue::from_f64(floor_xyz[1] as f64),
-                &JsValue::from_f64(floor_xyz[2] as f64)
-            )
-            .unwrap_or(JsValue::from_f64(0.0))
-            .as_f64()
-            .unwrap_or(0.0) as i32;
-        if hit == 0 {
+        let half = self.size[axis] * 0.5;
+        let edge = self.pos[axis] + if d > 0.0 { half } else { -half };
+        let from = (edge - d.signum() * SKIN).floor() as i32;
+        let to = (edge + d).floor() as i32;
+        let mut layer = from;
+        let mut hit = None;
+        while layer != to {
+            layer += d.signum() as i32;
+            let mut p = self.pos;
+            p[axis] = if d > 0.0 { layer as f32 - half } else { layer as f32 + 1.0 + half };
+            p[axis] += d.signum() * SKIN * 2.0;
+            if self.blocked(p, pick) {
+                hit = Some(layer);
+                break;
+            }
+        }
+        let Some(layer) = hit else {
+            self.pos[axis] += d;
             return;
+        };
+        if axis != 1 && self.is_ground {
+            let feet = self.pos[1] - self.size[1] * 0.5;
+            let mut up = self.pos;
+            up[1] += feet.floor() + 1.0 + SKIN - feet;
+            // rise in place before moving, so a lip over the head stops the step as well
+            let lift = up;
+            up[axis] += d;
+            if !self.blocked(lift, pick) && !self.blocked(up, pick) {
+                self.pos = up;
+                return;
+            }
         }
-        if axis == 1 && s < 0.0 {
+        self.pos[axis] = if d > 0.0 { layer as f32 - half - SKIN } else { layer as f32 + 1.0 + half + SKIN };
+        if axis == 1 && d < 0.0 {
             self.is_ground = true;
         }
-        let half = self.size[axis as usize] * 0.5;
-        let base = self.pos[axis as usize].floor() as i32;
-        self.pos[axis as usize] = clamp_to_face(self.pos[axis as usize], half, s, base);
-        self.vel[axis as usize] = 0.0;
+        self.vel[axis] = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn movement_is_instant_unless_smoothing_is_asked_for() {
+        let mut cam = Camera::new(0.0, 0.0, 0.0);
+        cam.set_mode(Mode::Fly);
+        cam.set_angles(0.0, 0.0);
+        cam.asdw(1, 1.0);
+        cam.step(0.01, &|_, _, _| 0);
+        // fly runs at 20x move_speed
+        let v = cam.vel;
+        assert!(((v[0] * v[0] + v[2] * v[2]).sqrt() - 240.0).abs() < 1e-3);
+        cam.asdw(1, 0.0);
+        cam.step(0.01, &|_, _, _| 0);
+        assert_eq!(cam.vel, [0.0; 3]);
+        cam.set_params(Params { accel: 12.0, ..cam.params() });
+        cam.asdw(1, 1.0);
+        cam.step(0.01, &|_, _, _| 0);
+        assert!((cam.vel[0] * cam.vel[0] + cam.vel[2] * cam.vel[2]).sqrt() < 50.0);
+    }
+
+    #[test]
+    fn update_projects_what_the_camera_faces() {
+        let mut cam = Camera::new(0.0, 0.0, 0.0);
+        cam.set_mode(Mode::Fly);
+        cam.set_angles(0.0, 0.0);
+        cam.update(1.5);
+        let m = *cam.matrix();
+        assert!(U::vis_sphere(&m, 0.0, 0.0, -20.0, 0.5));
+        assert!(!U::vis_sphere(&m, 0.0, 0.0, 20.0, 0.5));
+        // turning round swaps them
+        cam.set_angles(std::f32::consts::PI, 0.0);
+        cam.update(1.5);
+        let m = *cam.matrix();
+        assert!(!U::vis_sphere(&m, 0.0, 0.0, -20.0, 0.5));
+        assert!(U::vis_sphere(&m, 0.0, 0.0, 20.0, 0.5));
+    }
+
+    // floor below y = 0, walled in for x and z outside -5..5
+    fn room(x: f32, y: f32, z: f32) -> i32 {
+        (y < 0.0 || (y < 10.0 && !((-5.0..5.0).contains(&x) && (-5.0..5.0).contains(&z)))) as i32
+    }
+
+    #[test]
+    fn walking_lands_on_the_floor_and_stops_at_the_wall() {
+        let mut cam = Camera::new(0.0, 3.0, 0.0);
+        cam.set_mode(Mode::Walk);
+        cam.set_angles(0.3, 0.0);
+        for _ in 0..30 {
+            cam.step(1.0 / 60.0, &room);
+        }
+        assert!(cam.is_ground);
+        assert!((cam.pos[1] - (0.9 + SKIN)).abs() < 1e-4);
+        cam.asdw(1, 1.0);
+        for _ in 0..120 {
+            cam.step(1.0 / 60.0, &room);
+        }
+        // never inside a wall, and pressed flat against one
+        let limit = 5.0 - 0.4 - SKIN;
+        let [x, _, z] = cam.pos;
+        assert!(x.abs() <= limit + 1e-4 && z.abs() <= limit + 1e-4);
+        assert!((x.abs().max(z.abs()) - limit).abs() < 1e-4);
+        assert!(cam.is_ground);
+    }
+
+    #[test]
+    fn steps_up_a_voxel_unless_a_lip_blocks_the_head() {
+        // a one-voxel step at x = 2 with the floor below y = 0, optionally a lip at (1, 2) over the player
+        let world = |lip: bool| {
+            move |x: f32, y: f32, _: f32| (y < 0.0 || (x == 2.0 && y == 0.0) || (lip && x == 1.0 && y == 2.0)) as i32
+        };
+        for lip in [false, true] {
+            let mut cam = Camera::new(1.6 - SKIN, 0.9 + SKIN, 0.5);
+            cam.is_ground = true;
+            cam.collide(0, 0.9, &world(lip));
+            if lip {
+                assert!((cam.pos[0] - (1.6 - SKIN)).abs() < 1e-4);
+                assert!((cam.pos[1] - (0.9 + SKIN)).abs() < 1e-4);
+            } else {
+                assert!((cam.pos[0] - (2.5 - SKIN)).abs() < 1e-4);
+                assert!((cam.pos[1] - (1.9 + SKIN)).abs() < 1e-4);
+            }
+        }
     }
 }
@@ -1,21 +1,61 @@
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-use js_sys::Reflect;
+use crate::host::Atlas;
+use crate::mesher::{ Meshed, Mesher, mesh, greedy_quads };
+use crate::journal::Voxel;
+use crate::budget::Usage;
+use crate::world::World;
 use crate::utils as U;
 use web_sys::CanvasRenderingContext2d;
 use std::rc::Rc;
 use std::cell::RefCell;
 
-fn idx(x: usize, y: usize, z: usize, n: usize) -> usize {
-    x + (y + z * n) * n
-}
-
 struct ChunkState {
     is_meshed: bool,
-    count: u32,
     vox: Option<Vec<u8>>,
-    pos: Vec<f32>,
-    scl: Vec<f32>,
+    rgba: Option<Vec<u8>>,
+    tol: Option<u8>,
+    // faces as culled quads, which only the region can finish (see Chunk::cull)
+    quad: bool,
+    mesh: Meshed,
+}
+
+fn idx(x: i32, y: i32, z: i32) -> Option<usize> {
+    if [x, y, z].iter().any(|v| !(0..U::CHUNK).contains(v)) {
+        return None;
+    }
+    let n = U::CHUNK as usize;
+    Some(x as usize + (y as usize + z as usize * n) * n)
+}
+
+impl ChunkState {
+    fn write(&mut self, x: i32, y: i32, z: i32, rgb: Voxel) -> bool {
+        let Some(p) = idx(x, y, z) else {
+            return false;
+        };
+        let Some(vox) = self.vox.as_mut() else {
+            return false;
+        };
+        let v = rgb.is_some() as u8;
+        let mut changed = vox[p] != v;
+        vox[p] = v;
+        if let Some(buf) = self.rgba.as_mut() {
+            let px = rgb.map(|c| [c[0], c[1], c[2], 255]).unwrap_or([0; 4]);
+            changed |= buf[p * 4..p * 4 + 4] != px;
+            buf[p * 4..p * 4 + 4].copy_from_slice(&px);
+        }
+        changed
+    }
+    fn remesh(&mut self, x: f32, y: f32, z: f32) {
+        let n = U::CHUNK as usize;
+        let mut m = match (self.quad, self.tol, self.rgba.as_ref(), self.vox.as_ref()) {
+            (true, _, _, Some(vox)) => mesh(vox, n, Mesher::Quad),
+            (_, Some(t), Some(rgba), _) => mesh(rgba, n, Mesher::Color(t)),
+            (_, _, _, Some(vox)) => mesh(vox, n, Mesher::Box),
+            _ => Meshed::default(),
+        };
+        m.offset(x, y, z);
+        self.mesh = m;
+    }
 }
 
 #[wasm_bindgen]
@@ -31,78 +71,6 @@ pub struct Chunk {
     state: Rc<RefCell<ChunkState>>,
 }
 
-#[wasm_bindgen(js_name = greedyMesh)]
-pub fn greedy_mesh(src: &js_sys::Uint8Array, size: u32) -> js_sys::Object {
-    let n = size as usize;
-    let mut data = src.to_vec();
-    let mut pos: Vec<f32> = Vec::new();
-    let mut scl: Vec<f32> = Vec::new();
-    let mut count = 0u32;
-    let mut i = 0usize;
-    while i < n * n * n {
-        let xi = i % n;
-        let yi = (i / n) % n;
-        let zi = i / (n * n);
-        if data[i] > 0 {
-            let mut w = 1usize;
-            while xi + w < n && data[idx(xi + w, yi, zi, n)] > 0 {
-                w += 1;
-            }
-            let mut h = 1usize;
-            let mut ok = true;
-            while yi + h < n && ok {
-                for a in 0..w {
-                    if data[idx(xi + a, yi + h, zi, n)] == 0 {
-                        ok = false;
-                        break;
-                    }
-                }
-                if ok {
-                    h += 1;
-                }
-            }
-            let mut d = 1usize;
-            let mut ok2 = true;
-            while zi + d < n && ok2 {
-                for b in 0..h {
-                    for a in 0..w {
-                        if data[idx(xi + a, yi + b, zi + d, n)] == 0 {
-                            ok2 = false;
-                            break;
-                        }
-                    }
-                    if !ok2 {
-                        break;
-                    }
-                }
-                if ok2 {
-                    d += 1;
-                }
-            }
-            for k in 0..d {
-                for j in 0..h {
-                    for i2 in 0..w {
-                        data[idx(xi + i2, yi + j, zi + k, n)] = 0;
-                    }
-                }
-            }
-            pos.push((w as f32) * 0.5 + (xi as f32));
-            pos.push((h as f32) * 0.5 + (yi as f32));
-            pos.push((d as f32) * 0.5 + (zi as f32));
-            scl.push(w as f32);
-            scl.push(h as f32);
-            scl.push(d as f32);
-            count += 1;
-        }
-        i += 1;
-    }
-    let o = js_sys::Object::new();
-    let _ = js_sys::Reflect::set(&o, &"pos".into(), &js_sys::Float32Array::from(pos.as_slice()));
-    let _ = js_sys::Reflect::set(&o, &"scl".into(), &js_sys::Float32Array::from(scl.as_slice()));
-    let _ = js_sys::Reflect::set(&o, &"count".into(), &JsValue::from_f64(count as f64));
-    o
-}
-
 #[wasm_bindgen(js_name = createChunk)]
 pub fn create_chunk(i: i32, j: i32, k: i32) -> Chunk {
     let id = U::chunk_id(i, j, k);
@@ -120,86 +88,199 @@ pub fn create_chunk(i: i32, j: i32, k: i32) -> Chunk {
         state: Rc::new(
             RefCell::new(ChunkState {
                 is_meshed: false,
-                count: 0,
                 vox: None,
-                pos: Vec::new(),
-                scl: Vec::new(),
+                rgba: None,
+                tol: None,
+                quad: false,
+                mesh: Meshed::default(),
             })
         ),
     }
 }
 
-#[wasm_bindgen]
 impl Chunk {
-    #[wasm_bindgen(getter)]
-    pub fn pos(&self) -> js_sys::Float32Array {
-        js_sys::Float32Array::from(self.state.borrow().pos.as_slice())
+    pub fn fill<A: Atlas + ?Sized>(&self, atlas: &A) {
+        self.fill_with(atlas, &World::default(), Mesher::Box);
     }
-    #[wasm_bindgen(getter)]
-    pub fn scl(&self) -> js_sys::Float32Array {
-        js_sys::Float32Array::from(self.state.borrow().scl.as_slice())
+    // Mesher::Color keeps colour and meshes with greedy_color instead of greedy; world places
+    // the chunk's tile in the region atlas
+    pub fn fill_with<A: Atlas + ?Sized>(&self, atlas: &A, world: &World, mesher: Mesher) {
+        if self.is_meshed() {
+            return;
+        }
+        self.decode(atlas, world, mesher);
+        let mut state = self.state.borrow_mut();
+        state.remesh(self.x, self.y, self.z);
+        state.is_meshed = true;
     }
-    pub fn load(&mut self, ctx: &CanvasRenderingContext2d) {
+    // occupancy (and colour) without meshing, for neighbours a quad chunk is culled against;
+    // false when already decoded
+    pub fn decode<A: Atlas + ?Sized>(&self, atlas: &A, world: &World, mesher: Mesher) -> bool {
         let mut state = self.state.borrow_mut();
-        if state.is_meshed {
-            return;
+        if state.vox.is_some() {
+            return false;
         }
-        let ox = ((self.k & 3) * 1024 + self.i * 64) as f64;
-        let oy = ((self.k >> 2) * 1024 + self.j * 64) as f64;
-        let tile = ctx.get_image_data(ox, oy, 64.0, 64.0).unwrap();
-        let data = tile.data();
+        let tol = match mesher {
+            Mesher::Color(t) => Some(t),
+            _ => None,
+        };
+        let (ox, oy) = world.tile(self.i, self.j, self.k);
+        let tile = U::TILE as usize;
+        let data = atlas.read(ox, oy, tile as u32, tile as u32);
         let chunk_size = U::CHUNK as usize;
         let mut vox = vec![0u8; chunk_size * chunk_size * chunk_size];
+        let mut rgba = vec![0u8; if tol.is_some() { vox.len() * 4 } else { 0 }];
         let mut p = 0;
         for z in 0..chunk_size {
             for y in 0..chunk_size {
                 for x in 0..chunk_size {
-                    let px = ((z & 3) * 16 + x) as usize;
-                    let py = ((z >> 2) * 16 + y) as usize;
-                    let si = (py * 64 + px) * 4;
+                    let px = (z & 3) * chunk_size + x;
+                    let py = (z >> 2) * chunk_size + y;
+                    let si = (py * tile + px) * 4;
                     let alpha = data.get(si + 3).unwrap_or(&0);
                     vox[p] = if *alpha > 128 { 1 } else { 0 };
+                    if tol.is_some() {
+                        if let Some(px) = data.get(si..si + 4) {
+                            rgba[p * 4..p * 4 + 4].copy_from_slice(px);
+                        }
+                    }
                     p += 1;
                 }
             }
         }
-        let mesh_result = greedy_mesh(&js_sys::Uint8Array::from(vox.as_slice()), U::CHUNK as u32);
-        let pos_array: js_sys::Float32Array = Reflect::get(&mesh_result, &"pos".into())
-            .unwrap()
-            .unchecked_into();
-        let scl_array: js_sys::Float32Array = Reflect::get(&mesh_result, &"scl".into())
-            .unwrap()
-            .unchecked_into();
-        let count = Reflect::get(&mesh_result, &"count".into())
-            .unwrap()
-            .as_f64()
-            .unwrap_or(0.0) as u32;
-        let mut pos = vec![0.0f32; pos_array.length() as usize];
-        let mut scl = vec![0.0f32; scl_array.length() as usize];
-        pos_array.copy_to(&mut pos);
-        scl_array.copy_to(&mut scl);
-        for i in 0..count as usize {
-            let j = i * 3;
-            pos[j] += self.x;
-            pos[j + 1] += self.y;
-            pos[j + 2] += self.z;
-        }
-        state.count = count;
         state.vox = Some(vox);
-        state.pos = pos;
-        state.scl = scl;
+        state.rgba = tol.map(|_| rgba);
+        state.tol = tol;
+        state.quad = mesher == Mesher::Quad;
+        true
+    }
+    // takes occupancy, colour and mesh decoded elsewhere (a worker) as if fill_with had run
+    pub fn adopt(&self, vox: Vec<u8>, rgba: Option<Vec<u8>>, mesher: Mesher, mesh: Meshed) {
+        let mut state = self.state.borrow_mut();
+        state.vox = Some(vox);
+        state.rgba = rgba;
+        state.tol = match mesher {
+            Mesher::Color(t) => Some(t),
+            _ => None,
+        };
+        state.quad = mesher == Mesher::Quad;
+        state.mesh = mesh;
         state.is_meshed = true;
     }
+    // copies of what adopt takes, None until filled
+    pub fn parts(&self) -> Option<(Vec<u8>, Option<Vec<u8>>, Meshed)> {
+        let state = self.state.borrow();
+        let vox = state.vox.clone()?;
+        Some((vox, state.rgba.clone(), state.mesh.clone()))
+    }
+    // rgb = Some(..) places a voxel (colour only kept in colour mode), None clears it;
+    // false when out of range, not loaded yet, or nothing changed
+    pub fn set(&self, x: i32, y: i32, z: i32, rgb: Voxel) -> bool {
+        self.apply(&[([x, y, z], rgb)])
+    }
+    // several writes, one remesh
+    pub fn apply(&self, edits: &[([i32; 3], Voxel)]) -> bool {
+        let mut state = self.state.borrow_mut();
+        let mut changed = false;
+        for ([x, y, z], rgb) in edits.iter().copied() {
+            changed |= state.write(x, y, z, rgb);
+        }
+        if changed {
+            state.remesh(self.x, self.y, self.z);
+        }
+        changed
+    }
+    // None while not loaded; solid voxels outside colour mode read back as white
+    pub fn get(&self, x: i32, y: i32, z: i32) -> Option<Voxel> {
+        let state = self.state.borrow();
+        let vox = state.vox.as_ref()?;
+        let p = idx(x, y, z)?;
+        if vox[p] == 0 {
+            return Some(None);
+        }
+        let c = state.rgba.as_ref().map(|b| [b[p * 4], b[p * 4 + 1], b[p * 4 + 2]]);
+        Some(Some(c.unwrap_or([255; 3])))
+    }
+    pub fn remesh(&self) {
+        let mut state = self.state.borrow_mut();
+        if state.is_meshed {
+            state.remesh(self.x, self.y, self.z);
+        }
+    }
+    pub fn voxel(&self, x: i32, y: i32, z: i32) -> Option<u8> {
+        let Some(p) = idx(x, y, z) else {
+            return Some(0);
+        };
+        let state = self.state.borrow();
+        let vox = state.vox.as_ref()?;
+        Some(vox[p])
+    }
+    pub fn quads<F: Fn(i32, i32, i32) -> bool>(&self, outside: F) -> Option<Meshed> {
+        let state = self.state.borrow();
+        let vox = state.vox.as_ref()?;
+        let mut q = greedy_quads(vox, U::CHUNK as usize, outside);
+        q.offset(self.x, self.y, self.z);
+        Some(q)
+    }
+    // quad mode only: replaces the mesh with faces culled against outside, which answers
+    // occupancy for chunk-local coordinates beyond the chunk
+    pub fn cull<F: Fn(i32, i32, i32) -> bool>(&self, outside: F) {
+        if !self.state.borrow().quad {
+            return;
+        }
+        let Some(q) = self.quads(outside) else {
+            return;
+        };
+        self.state.borrow_mut().mesh = q;
+    }
+    pub fn is_meshed(&self) -> bool {
+        self.state.borrow().is_meshed
+    }
+    // heap the chunk owns: its buffers plus the Rc'd state (strong and weak counts in front);
+    // the Chunk handles themselves live in the region's map and queue, which it counts
+    pub fn usage(&self) -> Usage {
+        let state = self.state.borrow();
+        let cap = |v: &Option<Vec<u8>>| v.as_ref().map_or(0, |v| v.capacity());
+        let m = &state.mesh;
+        let floats = m.pos.capacity() + m.scl.capacity() + m.col.capacity() + m.dir.capacity();
+        Usage {
+            voxels: cap(&state.vox) + cap(&state.rgba),
+            meshes: floats * std::mem::size_of::<f32>(),
+            image: 0,
+            other: std::mem::size_of::<RefCell<ChunkState>>() + 2 * std::mem::size_of::<usize>(),
+        }
+    }
+    pub fn with_mesh<R>(&self, f: impl FnOnce(&Meshed) -> R) -> R {
+        f(&self.state.borrow().mesh)
+    }
+}
+
+#[wasm_bindgen]
+impl Chunk {
+    #[wasm_bindgen(getter)]
+    pub fn pos(&self) -> js_sys::Float32Array {
+        js_sys::Float32Array::from(self.state.borrow().mesh.pos.as_slice())
+    }
+    #[wasm_bindgen(getter)]
+    pub fn scl(&self) -> js_sys::Float32Array {
+        js_sys::Float32Array::from(self.state.borrow().mesh.scl.as_slice())
+    }
+    #[wasm_bindgen(getter)]
+    pub fn col(&self) -> js_sys::Float32Array {
+        js_sys::Float32Array::from(self.state.borrow().mesh.col.as_slice())
+    }
+    pub fn load(&mut self, ctx: &CanvasRenderingContext2d) {
+        self.fill(ctx);
+    }
     pub fn dispose(&mut self) {
         let mut state = self.state.borrow_mut();
         state.is_meshed = false;
         state.vox = None;
-        state.pos.clear();
-        state.scl.clear();
-        state.count = 0;
+        state.rgba = None;
+        state.mesh.clear();
     }
     pub fn count(&self) -> u32 {
-        self.state.borrow().count
+        self.state.borrow().mesh.cnt
     }
     pub fn vox(&self) -> JsValue {
         if let Some(ref vox) = self.state.borrow().vox {
@@ -0,0 +1,65 @@
+// services the core borrows from whatever embeds it (browser, native test, exporter)
+
+pub trait Atlas {
+    fn read(&self, x: u32, y: u32, w: u32, h: u32) -> Vec<u8>;
+}
+
+pub trait Clock {
+    fn now(&self) -> f64;
+}
+
+pub trait Upload {
+    fn attr(&mut self, key: &str, data: &[f32], size: i32);
+    // overwrite part of an attribute already sent with attr, starting at float offset `at`
+    fn patch(&mut self, key: &str, at: usize, data: &[f32]);
+}
+
+// where the view is, for picking the regions around and in front of it
+pub trait Eye {
+    fn pos(&self) -> [f32; 3];
+    fn mvp(&self) -> [f32; 16];
+}
+
+// runs fetches a few at a time, highest priority first; Task is the handle bump and abort take
+// and Done what the caller waits on until start's own Done settles
+pub trait Scheduler {
+    type Task: Clone;
+    type Done: Clone;
+    fn schedule(&self, start: Box<dyn FnMut() -> Self::Done>, priority: i32) -> Option<(Self::Task, Self::Done)>;
+    fn bump(&self, task: &Self::Task, priority: i32);
+    fn abort(&self, task: &Self::Task);
+}
+
+pub struct Pixels {
+    pub width: u32,
+    pub data: Vec<u8>,
+}
+
+impl Atlas for Pixels {
+    fn read(&self, x: u32, y: u32, w: u32, h: u32) -> Vec<u8> {
+        let mut out = vec![0u8; (w * h * 4) as usize];
+        let rows = (self.data.len() as u32) / (self.width * 4).max(1);
+        for dy in 0..h {
+            let sy = y + dy;
+            if sy >= rows {
+                break;
+            }
+            for dx in 0..w {
+                let sx = x + dx;
+                if sx >= self.width {
+                    break;
+                }
+                let si = ((sy * self.width + sx) * 4) as usize;
+                let di = ((dy * w + dx) * 4) as usize;
+                out[di..di + 4].copy_from_slice(&self.data[si..si + 4]);
+            }
+        }
+        out
+    }
+}
+
+impl<F: Fn() -> f64> Clock for F {
+    fn now(&self) -> f64 {
+        self()
+    }
+}
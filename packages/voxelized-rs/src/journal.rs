@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+
+// None is empty, Some(rgb) is solid; colour only matters to colour-mode meshes
+pub type Voxel = Option<[u8; 3]>;
+
+// one voxel change in region-local coordinates (0..REGION)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Edit {
+    pub at: [i32; 3],
+    pub old: Voxel,
+    pub new: Voxel,
+}
+
+pub struct Journal<T: Copy = Edit> {
+    edits: Vec<T>,
+    cursor: usize,
+}
+
+impl<T: Copy> Default for Journal<T> {
+    fn default() -> Self {
+        Journal { edits: Vec::new(), cursor: 0 }
+    }
+}
+
+impl<T: Copy> Journal<T> {
+    // a new edit drops whatever could still be redone
+    pub fn record(&mut self, e: T) {
+        self.edits.truncate(self.cursor);
+        self.edits.push(e);
+        self.cursor += 1;
+    }
+    pub fn undo(&mut self) -> Option<T> {
+        if self.cursor == 0 {
+            return None;
+        }
+        self.cursor -= 1;
+        Some(self.edits[self.cursor])
+    }
+    pub fn redo(&mut self) -> Option<T> {
+        let e = *self.edits.get(self.cursor)?;
+        self.cursor += 1;
+        Some(e)
+    }
+    pub fn can_undo(&self) -> bool {
+        self.cursor > 0
+    }
+    pub fn can_redo(&self) -> bool {
+        self.cursor < self.edits.len()
+    }
+    pub fn applied(&self) -> &[T] {
+        &self.edits[..self.cursor]
+    }
+    pub fn clear(&mut self) {
+        self.edits.clear();
+        self.cursor = 0;
+    }
+}
+
+const MAGIC: &[u8; 4] = b"VXD1";
+
+// net diff: "VXD1", u32 LE count, then x y z r g b a per voxel (a = 0 empty, 255 solid), sorted by x, y, z
+pub fn encode(diff: &BTreeMap<[i32; 3], Voxel>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + diff.len() * 7);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(diff.len() as u32).to_le_bytes());
+    for (at, v) in diff {
+        let [r, g, b] = v.unwrap_or([0; 3]);
+        out.extend_from_slice(&[at[0] as u8, at[1] as u8, at[2] as u8, r, g, b]);
+        out.push(if v.is_some() { 255 } else { 0 });
+    }
+    out
+}
+
+pub fn decode(bytes: &[u8]) -> Option<Vec<([i32; 3], Voxel)>> {
+    if bytes.len() < 8 || &bytes[..4] != MAGIC {
+        return None;
+    }
+    let n = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+    let body = &bytes[8..];
+    if body.len() != n * 7 {
+        return None;
+    }
+    let out = body
+        .chunks(7)
+        .map(|e| {
+            let at = [e[0] as i32, e[1] as i32, e[2] as i32];
+            (at, if e[6] > 128 { Some([e[3], e[4], e[5]]) } else { None })
+        })
+        .collect();
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_round_trips_and_rejects_a_bad_length() {
+        let diff = BTreeMap::from([([0, 1, 2], Some([9, 8, 7])), ([255, 0, 3], None)]);
+        let bytes = encode(&diff);
+        assert_eq!(bytes.len(), 8 + 2 * 7);
+        assert_eq!(decode(&bytes), Some(diff.into_iter().collect()));
+        assert!(decode(&bytes[..bytes.len() - 1]).is_none());
+        assert!(decode(b"VXD0\0\0\0\0").is_none());
+    }
+
+    #[test]
+    fn record_drops_what_could_be_redone() {
+        let mut j: Journal<i32> = Journal::default();
+        j.record(1);
+        j.record(2);
+        assert_eq!(j.undo(), Some(2));
+        j.record(3);
+        assert!(!j.can_redo());
+        assert_eq!(j.applied(), &[1, 3]);
+        assert_eq!((j.undo(), j.undo(), j.undo()), (Some(3), Some(1), None));
+        assert_eq!(j.redo(), Some(1));
+    }
+}
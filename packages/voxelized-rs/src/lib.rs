@@ -1,77 +1,79 @@
 use wasm_bindgen::prelude::*;
 
-fn idx(x: usize, y: usize, z: usize, n: usize) -> usize {
-    x + (y + z * n) * n
+mod utils;
+mod host;
+mod web;
+mod bake;
+mod budget;
+mod cache;
+mod camera;
+mod chunk;
+mod journal;
+mod manifest;
+mod mesh;
+mod mesher;
+mod nav;
+mod navmesh;
+mod queue;
+mod ray;
+mod region;
+mod slot;
+mod source;
+mod tour;
+mod world;
+mod worker;
+
+pub use host::{ Atlas, Clock, Eye, Scheduler, Upload, Pixels };
+pub use bake::{ Baked, bake };
+pub use budget::{ Budget, Stats, Usage };
+pub use cache::{ Cache, MemoryStore, Store };
+pub use camera::{ Camera, Mode, Params, Projection };
+pub use camera::create_camera as createCamera;
+pub use chunk::Chunk;
+pub use mesher::{ Meshed, Mesher, NORMALS, mesh, greedy, greedy_color, greedy_quads, greedy_mesh as greedyMesh };
+pub use mesh::Mesh;
+pub use mesh::create_mesh as createMesh;
+pub use ray::{ Hit, raycast };
+pub use journal::{ Edit, Journal, Voxel };
+pub use manifest::{ Entry, Manifest, hash };
+pub use nav::{ Nav, Cell, create_nav as createNav };
+pub use navmesh::{ Surface, surface };
+pub use queue::{ Lanes, Queues };
+pub use queue::create_queues as createQueues;
+pub use region::{ Region, Regions, create_regions as createRegions };
+pub use slot::{ Slots, create_slots as createSlots };
+pub use source::{ Retry, Source };
+pub use tour::{ Key, Tour };
+pub use world::World;
+pub use worker::{ Bridge, serve_atlas as serveAtlas };
+#[wasm_bindgen]
+pub fn range(n: u32) -> js_sys::Array {
+    web::range(n)
+}
+
+// regions per row of a world ({ x0, x1, ... } as createRegions takes), the default one without
+#[wasm_bindgen(js_name = ROW)]
+pub fn row(world: JsValue) -> i32 {
+    World::from_js(&world).row()
+}
+
+#[wasm_bindgen(js_name = REGION)]
+pub fn region() -> i32 {
+    utils::REGION
+}
+
+#[wasm_bindgen(js_name = SLOT)]
+pub fn slot() -> i32 {
+    utils::SLOT
+}
+
+#[wasm_bindgen(js_name = SCOPE)]
+pub fn scope() -> js_sys::Object {
+    World::default().to_js()
 }
 
-#[wasm_bindgen(js_name = greedyMesh)]
-pub fn greedy_mesh(src: &js_sys::Uint8Array, size: u32) -> js_sys::Object {
-    let n = size as usize;
-    let mut data = src.to_vec();
-    let mut pos: Vec<f32> = Vec::new();
-    let mut scl: Vec<f32> = Vec::new();
-    let mut count = 0u32;
-    let mut i = 0usize;
-    while i < n * n * n {
-        let xi = i % n;
-        let yi = (i / n) % n;
-        let zi = i / (n * n);
-        if data[i] > 0 {
-            let mut w = 1usize;
-            while xi + w < n && data[idx(xi + w, yi, zi, n)] > 0 {
-                w += 1;
-            }
-            let mut h = 1usize;
-            let mut ok = true;
-            while yi + h < n && ok {
-                for a in 0..w {
-                    if data[idx(xi + a, yi + h, zi, n)] == 0 {
-                        ok = false;
-                        break;
-                    }
-                }
-                if ok {
-                    h += 1;
-                }
-            }
-            let mut d = 1usize;
-            let mut ok2 = true;
-            while zi + d < n && ok2 {
-                for b in 0..h {
-                    for a in 0..w {
-                        if data[idx(xi + a, yi + b, zi + d, n)] == 0 {
-                            ok2 = false;
-                            break;
-                        }
-                    }
-                    if !ok2 {
-                        break;
-                    }
-                }
-                if ok2 {
-                    d += 1;
-                }
-            }
-            for k in 0..d {
-                for j in 0..h {
-                    for i2 in 0..w {
-                        data[idx(xi + i2, yi + j, zi + k, n)] = 0;
-                    }
-                }
-            }
-            pos.push((w as f32) * 0.5 + (xi as f32));
-            pos.push((h as f32) * 0.5 + (yi as f32));
-            pos.push((d as f32) * 0.5 + (zi as f32));
-            scl.push(w as f32);
-            scl.push(h as f32);
-            scl.push(d as f32);
-            count += 1;
-        }
-        i += 1;
-    }
-    let o = js_sys::Object::new();
-    let _ = js_sys::Reflect::set(&o, &"pos".into(), &js_sys::Float32Array::from(pos.as_slice()));
-    let _ = js_sys::Reflect::set(&o, &"scl".into(), &js_sys::Float32Array::from(scl.as_slice()));
-    let _ = js_sys::Reflect::set(&o, &"cnt".into(), &JsValue::from_f64(count as f64));
-    o
+// the hash a manifest lists for an atlas file, for tools writing one from JS
+#[wasm_bindgen(js_name = hashAtlas)]
+pub fn hash_atlas(bytes: &[u8]) -> String {
+    manifest::hash(bytes)
 }
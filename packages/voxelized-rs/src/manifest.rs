@@ -0,0 +1,120 @@
+use wasm_bindgen::prelude::*;
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use crate::world::World;
+
+// one tile the dataset has; lods are the zooms it was cut at, empty meaning the dataset's own
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct Entry {
+    pub i: i32,
+    pub j: i32,
+    pub hash: String,
+    pub lods: Vec<i32>,
+}
+
+// what a dataset contains, written next to the atlases by whatever cut them:
+//   { "version": "2026-10-19", "world": { x0, x1, y0, y1, region, origin, zoom },
+//     "lods": [17], "regions": [{ "i": 30, "j": 76, "hash": "9f3a…", "lods": [17] }, …] }
+// world uses World::from_js's keys, so it can be passed straight to createRegions
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct Manifest {
+    pub version: String,
+    pub world: Option<World>,
+    pub lods: Vec<i32>,
+    pub regions: Vec<Entry>,
+    #[serde(skip)]
+    index: HashMap<(i32, i32), usize>,
+}
+
+// FNV-1a 64 of an atlas file, hex; enough to notice a tile changed
+pub fn hash(bytes: &[u8]) -> String {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", h)
+}
+
+impl Manifest {
+    pub fn new(version: &str, world: Option<World>) -> Manifest {
+        Manifest { version: version.to_string(), world, ..Default::default() }
+    }
+    // lists (or replaces) a tile cut at lod from the atlas file's bytes
+    pub fn add(&mut self, i: i32, j: i32, lod: i32, bytes: &[u8]) {
+        let h = hash(bytes);
+        if !self.lods.contains(&lod) {
+            self.lods.push(lod);
+        }
+        match self.index.get(&(i, j)) {
+            Some(&at) => {
+                let e = &mut self.regions[at];
+                e.hash = h;
+                if !e.lods.contains(&lod) {
+                    e.lods.push(lod);
+                }
+            }
+            None => {
+                self.index.insert((i, j), self.regions.len());
+                self.regions.push(Entry { i, j, hash: h, lods: vec![lod] });
+            }
+        }
+    }
+    fn reindex(&mut self) {
+        self.index = self.regions
+            .iter()
+            .enumerate()
+            .map(|(at, e)| ((e.i, e.j), at))
+            .collect();
+    }
+    pub fn entry(&self, i: i32, j: i32) -> Option<&Entry> {
+        self.regions.get(*self.index.get(&(i, j))?)
+    }
+    // false for tiles the dataset doesn't have at this zoom, which then never get fetched
+    pub fn has(&self, i: i32, j: i32, lod: i32) -> bool {
+        self.entry(i, j).is_some_and(|e| e.lods.is_empty() || e.lods.contains(&lod))
+    }
+    // fills {version} and {hash} in an atlas url, or appends ?v=<hash> when it has neither
+    pub fn stamp(&self, url: &str, i: i32, j: i32) -> String {
+        let h = self.entry(i, j).map(|e| e.hash.as_str()).unwrap_or("");
+        if url.contains("{version}") || url.contains("{hash}") {
+            return url.replace("{version}", &self.version).replace("{hash}", h);
+        }
+        if h.is_empty() {
+            return url.to_string();
+        }
+        let sep = if url.contains('?') { '&' } else { '?' };
+        format!("{}{}v={}", url, sep, h)
+    }
+    // a parsed manifest object, e.g. JSON.parse of the file
+    pub fn from_js(v: JsValue) -> Option<Manifest> {
+        let mut m: Manifest = serde_wasm_bindgen::from_value(v).ok()?;
+        m.world = m.world.map(|w| w.fixed());
+        m.reindex();
+        Some(m)
+    }
+    pub fn to_js(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(self).unwrap_or(JsValue::UNDEFINED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_keeps_one_entry_per_tile() {
+        // voxelizer-js's manifest hash gives the same digest for these bytes
+        assert_eq!(hash(&[1, 2, 3]), "d0aa6218672cf5ab");
+        let mut m = Manifest::new("v1", None);
+        m.add(3, 4, 17, &[1]);
+        m.add(3, 4, 16, &[1, 2, 3]);
+        assert_eq!(m.regions.len(), 1);
+        assert_eq!(m.entry(3, 4).map(|e| e.hash.as_str()), Some("d0aa6218672cf5ab"));
+        assert!(m.has(3, 4, 16) && !m.has(3, 4, 15) && !m.has(4, 4, 17));
+        assert_eq!(m.stamp("a/{hash}.png", 3, 4), "a/d0aa6218672cf5ab.png");
+        assert_eq!(m.stamp("a.png?x=1", 9, 9), "a.png?x=1");
+    }
+}
@@ -1,159 +1,362 @@
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{ WebGl2RenderingContext, WebGlBuffer, WebGlProgram };
+use web_sys::{ WebGl2RenderingContext, WebGlProgram };
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::chunk::Chunk;
+use crate::host::Upload;
+use crate::mesher::Mesher;
+use crate::web::{ Buffers, Gl };
 
-#[wasm_bindgen]
-pub struct Mesh {
+// where one (slot, chunk) landed: cnt instances of its mesh, then zero-sized padding up to
+// cap so a remesh that grows a little still fits in place. Only edited chunks carry padding
+#[derive(Clone, Copy)]
+struct Span {
+    index: u32,
+    id: i32,
+    start: u32,
+    cnt: u32,
+    cap: u32,
+}
+
+// room for an edited chunk's instances, handed out when an edit first outgrows the span
+fn slack(cnt: u32) -> u32 {
+    if cnt == 0 { 0 } else { cnt + (cnt / 4).max(4) }
+}
+
+// one side of the double buffer; spans remember where each (slot, chunk) landed, in push order.
+// count includes the padding of edited chunks, since that is what gets drawn
+#[derive(Default)]
+struct Instances {
     pos: Vec<f32>,
     scl: Vec<f32>,
     aid: Vec<f32>,
+    col: Vec<f32>,
+    dir: Vec<f32>,
     count: u32,
-    _pos: Vec<f32>,
-    _scl: Vec<f32>,
-    _aid: Vec<f32>,
-    _count: u32,
+    spans: Vec<Span>,
+}
+
+// a chunk's instances laid out for the buffers, padding included
+struct Filled {
+    pos: Vec<f32>,
+    scl: Vec<f32>,
+    aid: Vec<f32>,
+    col: Vec<f32>,
+    dir: Vec<f32>,
+}
+
+impl Instances {
+    fn clear(&mut self) {
+        self.pos.clear();
+        self.scl.clear();
+        self.aid.clear();
+        self.col.clear();
+        self.dir.clear();
+        self.count = 0;
+        self.spans.clear();
+    }
+    // the chunk's mesh padded to cap instances
+    fn fill(chunk: &Chunk, index: u32, cap: u32) -> Filled {
+        chunk.with_mesh(|m| {
+            let n = cap as usize * 3;
+            let padded = |v: &[f32], len: usize, pad: f32| {
+                let mut out = Vec::with_capacity(len);
+                out.extend_from_slice(v);
+                out.resize(len, pad);
+                out
+            };
+            let col: &[f32] = if m.col.len() == m.pos.len() { &m.col } else { &[] };
+            let dir: &[f32] = if m.dir.len() == m.cnt as usize { &m.dir } else { &[] };
+            Filled {
+                pos: padded(&m.pos, n, 0.0),
+                scl: padded(&m.scl, n, 0.0),
+                aid: vec![index as f32; cap as usize],
+                col: padded(col, n, 1.0),
+                dir: padded(dir, cap as usize, 0.0),
+            }
+        })
+    }
+    fn push(&mut self, chunk: &Chunk, index: u32, mesher: Mesher) {
+        // drawn as meshed; padding waits until an edit needs it
+        let cnt = chunk.with_mesh(|m| m.cnt);
+        let f = Instances::fill(chunk, index, cnt);
+        self.pos.extend_from_slice(&f.pos);
+        self.scl.extend_from_slice(&f.scl);
+        self.aid.extend_from_slice(&f.aid);
+        if let Mesher::Color(_) = mesher {
+            self.col.extend_from_slice(&f.col);
+        }
+        if mesher == Mesher::Quad {
+            self.dir.extend_from_slice(&f.dir);
+        }
+        self.spans.push(Span { index, id: chunk.id, start: self.count, cnt, cap: cnt });
+        self.count += cnt;
+    }
+    // swaps the chunk's old instances for its current mesh; returns the span's (start, cap)
+    // and whether it had to grow, which moves every span after it
+    fn splice(&mut self, chunk: &Chunk, index: u32, mesher: Mesher) -> Option<(u32, u32, bool)> {
+        let at = self.spans.iter().position(|s| s.index == index && s.id == chunk.id)?;
+        let Span { start, cap: old, .. } = self.spans[at];
+        let cnt = chunk.with_mesh(|m| m.cnt);
+        let cap = if cnt <= old { old } else { slack(cnt) };
+        let (a, b) = (start as usize, (start + old) as usize);
+        let tinted = matches!(mesher, Mesher::Color(_)) && self.col.len() == self.pos.len();
+        let faced = mesher == Mesher::Quad && self.dir.len() == self.aid.len();
+        let f = Instances::fill(chunk, index, cap);
+        self.pos.splice(a * 3..b * 3, f.pos);
+        self.scl.splice(a * 3..b * 3, f.scl);
+        self.aid.splice(a..b, f.aid);
+        if tinted {
+            self.col.splice(a * 3..b * 3, f.col);
+        }
+        if faced {
+            self.dir.splice(a..b, f.dir);
+        }
+        self.spans[at].cnt = cnt;
+        self.spans[at].cap = cap;
+        if cap == old {
+            return Some((start, cap, false));
+        }
+        self.count = self.count - old + cap;
+        for s in self.spans[at + 1..].iter_mut() {
+            s.start = s.start - old + cap;
+        }
+        Some((start, cap, true))
+    }
+}
+
+struct MeshState {
+    front: Instances,
+    back: Instances,
+    // instances [lo, hi) of the front buffers changed in place since the last upload
+    dirty: Option<(u32, u32)>,
+    // front buffers changed size (commit, or an edit that grew/shrank a chunk)
+    stale: bool,
+    tol: Option<u8>,
+    quad: bool,
     is_ready: bool,
-    buf_pos: Option<WebGlBuffer>,
-    buf_scl: Option<WebGlBuffer>,
-    buf_aid: Option<WebGlBuffer>,
-    len_pos: u32,
-    len_scl: u32,
-    len_aid: u32,
+    bufs: Buffers,
+}
+
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct Mesh {
+    st: Rc<RefCell<MeshState>>,
 }
 
 #[wasm_bindgen(js_name = createMesh)]
 pub fn create_mesh() -> Mesh {
     Mesh {
-        pos: vec![0.0, 0.0, 0.0],
-        scl: vec![1.0, 1.0, 1.0],
-        aid: vec![0.0],
-        count: 1,
-        _pos: Vec::new(),
-        _scl: Vec::new(),
-        _aid: Vec::new(),
-        _count: 0,
-        is_ready: false,
-        buf_pos: None,
-        buf_scl: None,
-        buf_aid: None,
-        len_pos: 0,
-        len_scl: 0,
-        len_aid: 0,
+        st: Rc::new(
+            RefCell::new(MeshState {
+                front: Instances {
+                    pos: vec![0.0, 0.0, 0.0],
+                    scl: vec![1.0, 1.0, 1.0],
+                    aid: vec![0.0],
+                    col: vec![1.0, 1.0, 1.0],
+                    dir: vec![0.0],
+                    count: 1,
+                    spans: Vec::new(),
+                },
+                back: Instances::default(),
+                dirty: None,
+                stale: true,
+                tol: None,
+                quad: false,
+                is_ready: false,
+                bufs: Buffers::default(),
+            })
+        ),
     }
 }
 
-#[wasm_bindgen]
 impl Mesh {
-    pub fn merge(&mut self, chunk: &JsValue, index: u32) {
-        if let Ok(count_val) = js_sys::Reflect::get(chunk, &"count".into()) {
-            if let Some(count_fn) = count_val.dyn_ref::<js_sys::Function>() {
-                if let Ok(cnt_result) = count_fn.call0(chunk) {
-                    let cnt = cnt_result.as_f64().unwrap_or(0.0) as u32;
-                    if cnt > 0 {
-                        if
-                            let (Ok(pos_prop), Ok(scl_prop)) = (
-                                js_sys::Reflect::get(chunk, &"pos".into()),
-                                js_sys::Reflect::get(chunk, &"scl".into()),
-                            )
-                        {
-                            let pa = js_sys::Float32Array::new(&pos_prop);
-                            let sa = js_sys::Float32Array::new(&scl_prop);
-                            let mut tmp = vec![0.0f32; pa.length() as usize];
-                            pa.copy_to(&mut tmp[..]);
-                            self._pos.extend_from_slice(&tmp);
-                            let mut tmp2 = vec![0.0f32; sa.length() as usize];
-                            sa.copy_to(&mut tmp2[..]);
-                            self._scl.extend_from_slice(&tmp2);
-                            for _ in 0..cnt {
-                                self._aid.push(index as f32);
-                            }
-                            self._count += cnt;
-                        }
-                    }
-                }
+    pub fn push(&self, chunk: &Chunk, index: u32) {
+        let mesher = self.mesher();
+        self.st.borrow_mut().back.push(chunk, index, mesher);
+    }
+    // re-reads a remeshed chunk into both buffers so an in-flight rebuild doesn't resurrect the old mesh;
+    // only a chunk that outgrew its padding forces a full upload, anything else patches its range
+    pub fn patch(&self, chunk: &Chunk, index: u32) -> bool {
+        let mesher = self.mesher();
+        let mut st = self.st.borrow_mut();
+        let st = &mut *st;
+        st.back.splice(chunk, index, mesher);
+        let Some((start, cap, grew)) = st.front.splice(chunk, index, mesher) else {
+            return false;
+        };
+        if grew {
+            st.stale = true;
+        } else if cap > 0 {
+            let (lo, hi) = st.dirty.unwrap_or((start, start + cap));
+            st.dirty = Some((lo.min(start), hi.max(start + cap)));
+        }
+        true
+    }
+    pub fn upload<U: Upload>(&self, u: &mut U) -> u32 {
+        let mut st = self.st.borrow_mut();
+        let st = &mut *st;
+        let f = &mut st.front;
+        if f.count == 0 {
+            f.pos.extend_from_slice(&[0.0, 0.0, 0.0]);
+            f.scl.extend_from_slice(&[1.0, 1.0, 1.0]);
+            f.aid.push(0.0);
+            f.col.extend_from_slice(&[1.0, 1.0, 1.0]);
+            f.dir.push(0.0);
+            f.count = 1;
+            st.stale = true;
+        }
+        let tinted = st.tol.is_some() && f.col.len() == f.pos.len();
+        let faced = st.quad && f.dir.len() == f.aid.len();
+        if st.stale {
+            u.attr("scl", &f.scl, 3);
+            u.attr("pos", &f.pos, 3);
+            u.attr("aid", &f.aid, 1);
+            if tinted {
+                u.attr("col", &f.col, 3);
+            }
+            if faced {
+                u.attr("dir", &f.dir, 1);
+            }
+        } else if let Some((lo, hi)) = st.dirty {
+            let (a, b) = (lo as usize, hi as usize);
+            u.patch("scl", a * 3, &f.scl[a * 3..b * 3]);
+            u.patch("pos", a * 3, &f.pos[a * 3..b * 3]);
+            u.patch("aid", a, &f.aid[a..b]);
+            if tinted {
+                u.patch("col", a * 3, &f.col[a * 3..b * 3]);
+            }
+            if faced {
+                u.patch("dir", a, &f.dir[a..b]);
             }
         }
+        st.stale = false;
+        st.dirty = None;
+        f.count
+    }
+    pub fn ready(&self) -> bool {
+        self.st.borrow().is_ready
+    }
+    pub fn tolerance(&self) -> Option<u8> {
+        self.st.borrow().tol
+    }
+    // what the chunks pushed into this mesh are meshed with; quads win over colour
+    pub fn mesher(&self) -> Mesher {
+        let st = self.st.borrow();
+        match (st.quad, st.tol) {
+            (true, _) => Mesher::Quad,
+            (_, Some(t)) => Mesher::Color(t),
+            _ => Mesher::Box,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Mesh {
+    pub fn merge(&mut self, chunk: &Chunk, index: u32) {
+        self.push(chunk, index);
+    }
+    // colour mode: chunks meshed after this merge by colour and upload a per-instance `col`
+    pub fn colors(&mut self, tol: Option<u8>) {
+        self.st.borrow_mut().tol = tol;
+    }
+    // quad mode: chunks merged after this as greedy face quads culled against their neighbours,
+    // each with a per-instance `dir` (0..6 for +x -x +y -y +z -z); pos is the face centre and
+    // scl its extent, 0 along the normal
+    pub fn quads(&mut self, on: bool) {
+        self.st.borrow_mut().quad = on;
     }
     pub fn reset(&mut self) {
-        self._pos.clear();
-        self._scl.clear();
-        self._aid.clear();
-        self._count = 0;
+        self.st.borrow_mut().back.clear();
     }
     pub fn commit(&mut self) -> bool {
-        if self._count == 0 {
-            return false;
+        {
+            let mut st = self.st.borrow_mut();
+            if st.back.count == 0 {
+                return false;
+            }
+            let st = &mut *st;
+            std::mem::swap(&mut st.front, &mut st.back);
+            st.stale = true;
+            st.dirty = None;
+            st.is_ready = true;
         }
-        std::mem::swap(&mut self.pos, &mut self._pos);
-        std::mem::swap(&mut self.scl, &mut self._scl);
-        std::mem::swap(&mut self.aid, &mut self._aid);
-        self.count = self._count;
         self.reset();
-        self.is_ready = true;
         true
     }
     pub fn draw(&mut self, c: &JsValue, pg: &JsValue) -> u32 {
         let c: WebGl2RenderingContext = c.clone().unchecked_into();
         let pg: WebGlProgram = pg.clone().unchecked_into();
-        if self.count == 0 {
-            self.pos.push(0.0);
-            self.pos.push(0.0);
-            self.pos.push(0.0);
-            self.scl.push(1.0);
-            self.scl.push(1.0);
-            self.scl.push(1.0);
-            self.aid.push(0.0);
-            self.count = 1;
-        }
-        let scl = self.scl.clone();
-        let pos = self.pos.clone();
-        let aid = self.aid.clone();
-        self.attr(&c, &pg, &scl, "scl", 3);
-        self.attr(&c, &pg, &pos, "pos", 3);
-        self.attr(&c, &pg, &aid, "aid", 1);
-        self.count
+        let mut bufs = std::mem::take(&mut self.st.borrow_mut().bufs);
+        let count = self.upload(&mut Gl { c: &c, pg: &pg, bufs: &mut bufs });
+        self.st.borrow_mut().bufs = bufs;
+        count
     }
     pub fn count(&self) -> u32 {
-        self.count
-    }
-    pub fn isReady(&self) -> bool {
-        self.is_ready
-    }
-    fn attr(
-        &mut self,
-        c: &WebGl2RenderingContext,
-        pg: &WebGlProgram,
-        data: &[f32],
-        key: &str,
-        size: i32
-    ) {
-        let loc = c.get_attrib_location(pg, key) as u32;
-        let array = js_sys::Float32Array::from(data);
-        let (buf, len_slot) = match key {
-            "pos" => (&mut self.buf_pos, &mut self.len_pos),
-            "scl" => (&mut self.buf_scl, &mut self.len_scl),
-            _ => (&mut self.buf_aid, &mut self.len_aid),
-        };
-        if buf.is_none() {
-            *buf = c.create_buffer();
+        self.st.borrow().front.count
+    }
+    #[wasm_bindgen(js_name = isReady)]
+    pub fn is_ready(&self) -> bool {
+        self.ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::create_chunk;
+    use crate::mesher::Meshed;
+
+    #[derive(Default)]
+    struct Rec {
+        full: Vec<(String, usize)>,
+        patched: Vec<(String, usize, usize)>,
+    }
+
+    impl Upload for Rec {
+        fn attr(&mut self, key: &str, data: &[f32], _: i32) {
+            self.full.push((key.to_string(), data.len()));
         }
-        c.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, buf.as_ref());
-        if *len_slot != array.length() {
-            c.buffer_data_with_array_buffer_view(
-                WebGl2RenderingContext::ARRAY_BUFFER,
-                &array,
-                WebGl2RenderingContext::DYNAMIC_DRAW
-            );
-            *len_slot = array.length();
-            c.enable_vertex_attrib_array(loc);
-            c.vertex_attrib_pointer_with_i32(loc, size, WebGl2RenderingContext::FLOAT, false, 0, 0);
-            c.vertex_attrib_divisor(loc, 1);
-        } else {
-            c.buffer_sub_data_with_i32_and_array_buffer_view(
-                WebGl2RenderingContext::ARRAY_BUFFER,
-                0,
-                &array
-            );
+        fn patch(&mut self, key: &str, at: usize, data: &[f32]) {
+            self.patched.push((key.to_string(), at, data.len()));
         }
     }
+
+    fn boxes(c: &Chunk, n: u32) {
+        let m = Meshed { pos: vec![0.0; n as usize * 3], scl: vec![1.0; n as usize * 3], cnt: n, ..Default::default() };
+        c.adopt(vec![0; 4096], None, Mesher::Box, m);
+    }
+
+    #[test]
+    fn growing_within_the_padding_patches_one_range() {
+        let mut mesh = create_mesh();
+        let (a, b) = (create_chunk(0, 0, 0), create_chunk(1, 0, 0));
+        boxes(&a, 4);
+        boxes(&b, 4);
+        mesh.push(&a, 0);
+        mesh.push(&b, 0);
+        mesh.commit();
+        // unedited chunks draw only their own instances
+        assert_eq!(mesh.upload(&mut Rec::default()), 8);
+        // the first edit that outgrows b lays it out again with room to spare
+        boxes(&b, 5);
+        assert!(mesh.patch(&b, 0));
+        let mut rec = Rec::default();
+        assert_eq!(mesh.upload(&mut rec), 4 + 9);
+        assert!(rec.full.contains(&("pos".to_string(), 13 * 3)));
+        // later edits fit the padding and only patch b's range
+        boxes(&b, 8);
+        assert!(mesh.patch(&b, 0));
+        let mut rec = Rec::default();
+        assert_eq!(mesh.upload(&mut rec), 13);
+        assert!(rec.full.is_empty());
+        assert!(rec.patched.contains(&("pos".to_string(), 4 * 3, 9 * 3)));
+        // shrinking never moves anything either
+        boxes(&a, 2);
+        assert!(mesh.patch(&a, 0));
+        let mut rec = Rec::default();
+        assert_eq!(mesh.upload(&mut rec), 13);
+        assert!(rec.full.is_empty() && rec.patched.contains(&("pos".to_string(), 0, 4 * 3)));
+    }
 }
@@ -0,0 +1,463 @@
+use wasm_bindgen::prelude::*;
+
+// face directions for quads: 0 +x, 1 -x, 2 +y, 3 -y, 4 +z, 5 -z
+pub const NORMALS: [[f32; 3]; 6] = [
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mesher {
+    Box,
+    Color(u8),
+    Quad,
+}
+
+impl Mesher {
+    // only quads are culled against the next chunk, so only they go stale when it changes
+    pub fn reads_neighbours(self) -> bool {
+        matches!(self, Mesher::Quad)
+    }
+}
+
+// instances shared by every mesher: centre `pos` and extent `scl` per instance,
+// plus `col` (rgb 0..1) from Color and `dir` (face index) from Quad
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct Meshed {
+    pub pos: Vec<f32>,
+    pub scl: Vec<f32>,
+    pub col: Vec<f32>,
+    pub dir: Vec<f32>,
+    pub cnt: u32,
+}
+
+impl Meshed {
+    fn cube(&mut self, at: [usize; 3], size: [usize; 3]) {
+        for a in 0..3 {
+            self.pos.push((size[a] as f32) * 0.5 + (at[a] as f32));
+        }
+        self.scl.extend_from_slice(&[size[0] as f32, size[1] as f32, size[2] as f32]);
+        self.cnt += 1;
+    }
+    fn quad(&mut self, face: usize, plane: usize, rect: [usize; 4]) {
+        let [u0, v0, w, h] = rect;
+        let d = face / 2;
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+        let mut p = [0.0f32; 3];
+        let mut s = [0.0f32; 3];
+        p[d] = plane as f32;
+        p[u] = (u0 as f32) + (w as f32) * 0.5;
+        p[v] = (v0 as f32) + (h as f32) * 0.5;
+        s[u] = w as f32;
+        s[v] = h as f32;
+        self.pos.extend_from_slice(&p);
+        self.scl.extend_from_slice(&s);
+        self.dir.push(face as f32);
+        self.cnt += 1;
+    }
+    pub fn offset(&mut self, x: f32, y: f32, z: f32) {
+        for p in self.pos.chunks_mut(3) {
+            p[0] += x;
+            p[1] += y;
+            p[2] += z;
+        }
+    }
+    pub fn clear(&mut self) {
+        *self = Meshed::default();
+    }
+    pub fn to_js(&self) -> js_sys::Object {
+        let o = js_sys::Object::new();
+        let _ = js_sys::Reflect::set(&o, &"pos".into(), &js_sys::Float32Array::from(self.pos.as_slice()));
+        let _ = js_sys::Reflect::set(&o, &"scl".into(), &js_sys::Float32Array::from(self.scl.as_slice()));
+        if !self.col.is_empty() {
+            let _ = js_sys::Reflect::set(&o, &"col".into(), &js_sys::Float32Array::from(self.col.as_slice()));
+        }
+        if !self.dir.is_empty() {
+            let _ = js_sys::Reflect::set(&o, &"dir".into(), &js_sys::Float32Array::from(self.dir.as_slice()));
+        }
+        let _ = js_sys::Reflect::set(&o, &"cnt".into(), &JsValue::from_f64(self.cnt as f64));
+        o
+    }
+}
+
+fn idx(x: usize, y: usize, z: usize, n: usize) -> usize {
+    x + (y + z * n) * n
+}
+
+// src is n³ occupancy (x fastest) for Box and Quad, n³ rgba for Color; works for 16³ chunks and 256³ regions.
+// A src too short for n meshes to nothing
+pub fn mesh(src: &[u8], n: usize, mesher: Mesher) -> Meshed {
+    let per = if let Mesher::Color(_) = mesher { 4 } else { 1 };
+    let need = n.checked_mul(n).and_then(|v| v.checked_mul(n)).and_then(|v| v.checked_mul(per));
+    if need.is_none_or(|need| src.len() < need) {
+        return Meshed::default();
+    }
+    match mesher {
+        Mesher::Box => greedy(src, n),
+        Mesher::Color(tol) => greedy_color(src, n, tol),
+        Mesher::Quad => greedy_quads(src, n, |_, _, _| false),
+    }
+}
+
+// mode: "box" (default), "color" or "quad"; tol only applies to "color". src must hold size³
+// bytes (size³ rgba for "color"), otherwise the result is empty
+#[wasm_bindgen(js_name = greedyMesh)]
+pub fn greedy_mesh(src: &js_sys::Uint8Array, size: u32, mode: Option<String>, tol: Option<u8>) -> js_sys::Object {
+    let mesher = match mode.as_deref() {
+        Some("color") => Mesher::Color(tol.unwrap_or(0)),
+        Some("quad") => Mesher::Quad,
+        _ => Mesher::Box,
+    };
+    mesh(&src.to_vec(), size as usize, mesher).to_js()
+}
+
+// rows of n bits along x, one row per (y, z), packed into u64 words
+fn span(lo: usize, len: usize) -> u64 {
+    if len >= 64 { !0 } else { ((1u64 << len) - 1) << lo }
+}
+
+// one bit per non-zero byte of an 8-byte group
+fn pack8(b: &[u8]) -> u64 {
+    let x = u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]);
+    let lo = 0x7f7f_7f7f_7f7f_7f7fu64;
+    let y = (((x & lo) + lo) | x) & !lo;
+    (y >> 7).wrapping_mul(0x0102_0408_1020_4080) >> 56
+}
+
+fn run(row: &[u64], x0: usize, n: usize) -> usize {
+    let mut x = x0;
+    while x < n {
+        let (w, b) = (x / 64, x % 64);
+        let ones = (!(row[w] >> b)).trailing_zeros() as usize;
+        let ones = ones.min(64 - b);
+        x += ones;
+        if ones < 64 - b {
+            break;
+        }
+    }
+    x.min(n) - x0
+}
+
+fn full(row: &[u64], x0: usize, len: usize) -> bool {
+    let (mut x, end) = (x0, x0 + len);
+    while x < end {
+        let (w, b) = (x / 64, x % 64);
+        let l = (64 - b).min(end - x);
+        let m = span(b, l);
+        if row[w] & m != m {
+            return false;
+        }
+        x += l;
+    }
+    true
+}
+
+fn clear(row: &mut [u64], x0: usize, len: usize) {
+    let (mut x, end) = (x0, x0 + len);
+    while x < end {
+        let (w, b) = (x / 64, x % 64);
+        let l = (64 - b).min(end - x);
+        row[w] &= !span(b, l);
+        x += l;
+    }
+}
+
+pub fn greedy(src: &[u8], n: usize) -> Meshed {
+    let words = n.div_ceil(64);
+    let mut rows = vec![0u64; n * n * words];
+    for (r, line) in src.chunks(n).take(n * n).enumerate() {
+        for (w, part) in line.chunks(64).enumerate() {
+            let mut bits = 0u64;
+            let mut it = part.chunks_exact(8);
+            for (k, b) in it.by_ref().enumerate() {
+                bits |= pack8(b) << (k * 8);
+            }
+            let tail = part.len() & !7;
+            for (b, v) in it.remainder().iter().enumerate() {
+                bits |= ((*v > 0) as u64) << (tail + b);
+            }
+            rows[r * words + w] = bits;
+        }
+    }
+    let at = |y: usize, z: usize| (y + z * n) * words;
+    let mut out = Meshed::default();
+    for zi in 0..n {
+        for yi in 0..n {
+            let r = at(yi, zi);
+            while let Some(w0) = rows[r..r + words].iter().position(|v| *v != 0) {
+                let xi = w0 * 64 + (rows[r + w0].trailing_zeros() as usize);
+                let w = run(&rows[r..r + words], xi, n);
+                let mut h = 1usize;
+                while yi + h < n && full(&rows[at(yi + h, zi)..], xi, w) {
+                    h += 1;
+                }
+                let mut d = 1usize;
+                while zi + d < n && (0..h).all(|b| full(&rows[at(yi + b, zi + d)..], xi, w)) {
+                    d += 1;
+                }
+                for k in 0..d {
+                    for b in 0..h {
+                        let s = at(yi + b, zi + k);
+                        clear(&mut rows[s..s + words], xi, w);
+                    }
+                }
+                out.cube([xi, yi, zi], [w, h, d]);
+            }
+        }
+    }
+    out
+}
+
+// like greedy, but a box only grows over voxels whose rgb stays within tol of its seed,
+// and each box carries the mean colour (0..1) of the voxels it covers
+pub fn greedy_color(rgba: &[u8], n: usize, tol: u8) -> Meshed {
+    let mut live: Vec<bool> = (0..n * n * n).map(|i| rgba[i * 4 + 3] > 128).collect();
+    let mut out = Meshed::default();
+    let close = |a: usize, b: usize| {
+        (0..3).all(|c| rgba[a * 4 + c].abs_diff(rgba[b * 4 + c]) <= tol)
+    };
+    for i in 0..n * n * n {
+        if !live[i] {
+            continue;
+        }
+        let xi = i % n;
+        let yi = (i / n) % n;
+        let zi = i / (n * n);
+        let ok = |live: &[bool], j: usize| live[j] && close(i, j);
+        let mut w = 1usize;
+        while xi + w < n && ok(&live, idx(xi + w, yi, zi, n)) {
+            w += 1;
+        }
+        let mut h = 1usize;
+        while yi + h < n && (0..w).all(|a| ok(&live, idx(xi + a, yi + h, zi, n))) {
+            h += 1;
+        }
+        let mut d = 1usize;
+        while
+            zi + d < n &&
+            (0..h).all(|b| (0..w).all(|a| ok(&live, idx(xi + a, yi + b, zi + d, n))))
+        {
+            d += 1;
+        }
+        let mut sum = [0u32; 3];
+        for k in 0..d {
+            for j in 0..h {
+                for i2 in 0..w {
+                    let p = idx(xi + i2, yi + j, zi + k, n);
+                    live[p] = false;
+                    for (c, s) in sum.iter_mut().enumerate() {
+                        *s += rgba[p * 4 + c] as u32;
+                    }
+                }
+            }
+        }
+        let m = ((w * h * d) as f32) * 255.0;
+        out.cube([xi, yi, zi], [w, h, d]);
+        out.col.extend_from_slice(&[(sum[0] as f32) / m, (sum[1] as f32) / m, (sum[2] as f32) / m]);
+    }
+    out
+}
+
+// outside answers occupancy for coordinates beyond the n³ block (neighbouring chunks)
+pub fn greedy_quads<F: Fn(i32, i32, i32) -> bool>(data: &[u8], n: usize, outside: F) -> Meshed {
+    let solid = |p: [i32; 3]| -> bool {
+        let m = n as i32;
+        if p.iter().any(|c| *c < 0 || *c >= m) {
+            return outside(p[0], p[1], p[2]);
+        }
+        data[(p[0] + (p[1] + p[2] * m) * m) as usize] > 0
+    };
+    let mut out = Meshed::default();
+    let mut mask = vec![false; n * n];
+    for d in 0..3 {
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+        for side in 0..2 {
+            let step = if side == 0 { 1 } else { -1 };
+            for s in 0..n {
+                for b in 0..n {
+                    for a in 0..n {
+                        let mut p = [0i32; 3];
+                        p[d] = s as i32;
+                        p[u] = a as i32;
+                        p[v] = b as i32;
+                        let mut q = p;
+                        q[d] += step;
+                        mask[a + b * n] = solid(p) && !solid(q);
+                    }
+                }
+                let plane = if side == 0 { s + 1 } else { s };
+                for b in 0..n {
+                    let mut a = 0;
+                    while a < n {
+                        if !mask[a + b * n] {
+                            a += 1;
+                            continue;
+                        }
+                        let mut w = 1;
+                        while a + w < n && mask[a + w + b * n] {
+                            w += 1;
+                        }
+                        let mut h = 1;
+                        'grow: while b + h < n {
+                            for k in 0..w {
+                                if !mask[a + k + (b + h) * n] {
+                                    break 'grow;
+                                }
+                            }
+                            h += 1;
+                        }
+                        for y in 0..h {
+                            for x in 0..w {
+                                mask[a + x + (b + y) * n] = false;
+                            }
+                        }
+                        out.quad(d * 2 + side, plane, [a, b, w, h]);
+                        a += w;
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the voxel-by-voxel mesher greedy replaced, kept as the reference output
+    fn scan(src: &[u8], n: usize) -> (Vec<f32>, Vec<f32>, u32) {
+        let mut data = src.to_vec();
+        let mut pos: Vec<f32> = Vec::new();
+        let mut scl: Vec<f32> = Vec::new();
+        let mut count = 0u32;
+        let mut i = 0usize;
+        while i < n * n * n {
+            let xi = i % n;
+            let yi = (i / n) % n;
+            let zi = i / (n * n);
+            if data[i] > 0 {
+                let mut w = 1usize;
+                while xi + w < n && data[idx(xi + w, yi, zi, n)] > 0 {
+                    w += 1;
+                }
+                let mut h = 1usize;
+                let mut ok = true;
+                while yi + h < n && ok {
+                    for a in 0..w {
+                        if data[idx(xi + a, yi + h, zi, n)] == 0 {
+                            ok = false;
+                            break;
+                        }
+                    }
+                    if ok {
+                        h += 1;
+                    }
+                }
+                let mut d = 1usize;
+                let mut ok2 = true;
+                while zi + d < n && ok2 {
+                    for b in 0..h {
+                        for a in 0..w {
+                            if data[idx(xi + a, yi + b, zi + d, n)] == 0 {
+                                ok2 = false;
+                                break;
+                            }
+                        }
+                        if !ok2 {
+                            break;
+                        }
+                    }
+                    if ok2 {
+                        d += 1;
+                    }
+                }
+                for k in 0..d {
+                    for j in 0..h {
+                        for i2 in 0..w {
+                            data[idx(xi + i2, yi + j, zi + k, n)] = 0;
+                        }
+                    }
+                }
+                pos.push((w as f32) * 0.5 + (xi as f32));
+                pos.push((h as f32) * 0.5 + (yi as f32));
+                pos.push((d as f32) * 0.5 + (zi as f32));
+                scl.push(w as f32);
+                scl.push(h as f32);
+                scl.push(d as f32);
+                count += 1;
+            }
+            i += 1;
+        }
+        (pos, scl, count)
+    }
+
+    fn boxes(m: Meshed) -> (Vec<f32>, Vec<f32>, u32) {
+        (m.pos, m.scl, m.cnt)
+    }
+
+    fn noise(n: usize, seed: u32, fill: u32) -> Vec<u8> {
+        let mut s = seed;
+        (0..n * n * n)
+            .map(|_| {
+                s ^= s << 13;
+                s ^= s >> 17;
+                s ^= s << 5;
+                ((s % 100) < fill) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn greedy_matches_scan() {
+        for n in [1, 2, 16, 63, 64, 65, 80, 130] {
+            for (seed, fill) in [(1, 0), (7, 10), (13, 50), (21, 90), (42, 100)] {
+                let src = noise(n, seed, fill);
+                assert_eq!(boxes(greedy(&src, n)), scan(&src, n), "n={} fill={}", n, fill);
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_matches_scan_on_city_blocks() {
+        let n = 256;
+        let src: Vec<u8> = (0..n * n * n)
+            .map(|i| {
+                let (x, y, z) = (i % n, (i / n) % n, i / (n * n));
+                let top = 40 + ((x / 32 + z / 32) % 5) * 20;
+                (y == 0 || (y < top && x % 32 < 28 && z % 32 < 28)) as u8
+            })
+            .collect();
+        assert_eq!(boxes(greedy(&src, n)), scan(&src, n));
+    }
+
+    #[test]
+    fn quads_of_adjacent_solid_chunks_share_no_face() {
+        let n = 16;
+        let full = vec![1u8; n * n * n];
+        let inside = |c: i32| c >= 0 && c < n as i32;
+        // a is the chunk at x 0..16, b the one at x 16..32
+        let a = greedy_quads(&full, n, |x, y, z| x == n as i32 && inside(y) && inside(z));
+        let b = greedy_quads(&full, n, |x, y, z| x == -1 && inside(y) && inside(z));
+        let faces = |m: &Meshed| (0..m.cnt as usize).map(|q| m.dir[q] as usize).collect::<Vec<_>>();
+        assert_eq!(faces(&a), vec![1, 2, 3, 4, 5]);
+        assert_eq!(faces(&b), vec![0, 2, 3, 4, 5]);
+        // alone, each would have all six
+        assert_eq!(greedy_quads(&full, n, |_, _, _| false).cnt, 6);
+    }
+
+    #[test]
+    fn short_sources_mesh_to_nothing() {
+        let vox = vec![1u8; 16 * 16 * 16];
+        assert!(mesh(&vox, 16, Mesher::Box).cnt > 0);
+        assert_eq!(mesh(&vox[1..], 16, Mesher::Box).cnt, 0);
+        assert_eq!(mesh(&vox[1..], 16, Mesher::Quad).cnt, 0);
+        // colour needs four bytes a voxel
+        assert_eq!(mesh(&vox, 16, Mesher::Color(0)).cnt, 0);
+        assert_eq!(mesh(&vox, usize::MAX, Mesher::Box).cnt, 0);
+    }
+}
@@ -0,0 +1,411 @@
+use wasm_bindgen::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{ BinaryHeap, HashMap };
+use crate::camera::Camera;
+use crate::region::Regions;
+use crate::navmesh::surface;
+use crate::utils as U;
+use crate::web as W;
+
+// walkable cells are integer voxel positions an agent can stand in (feet at y)
+pub type Cell = [i32; 3];
+
+const MOVES: [[i32; 2]; 4] = [[1, 0], [-1, 0], [0, 1], [0, -1]];
+const CELLS: usize = (U::CHUNK * U::CHUNK * U::CHUNK) as usize;
+const NODES: usize = 50_000;
+// new chunk graphs a single query may build before giving up
+const BUILDS: usize = 512;
+const LOOKAHEAD: usize = 32;
+
+pub fn cluster_of(c: Cell) -> Cell {
+    [c[0].div_euclid(U::CHUNK), c[1].div_euclid(U::CHUNK), c[2].div_euclid(U::CHUNK)]
+}
+
+fn near(a: Cell, b: Cell) -> bool {
+    (a[0] - b[0]).abs() <= 1 && (a[1] - b[1]).abs() <= 1 && (a[2] - b[2]).abs() <= 1
+}
+
+// abstract graph of one chunk: entrance cells, the cost between them inside the chunk,
+// and the cell each one steps to across the border
+#[derive(Default)]
+struct Cluster {
+    intra: HashMap<Cell, Vec<(Cell, u32)>>,
+    inter: HashMap<Cell, Vec<Cell>>,
+}
+
+#[wasm_bindgen]
+pub struct Nav {
+    height: i32,
+    clusters: HashMap<Cell, Cluster>,
+}
+
+#[wasm_bindgen(js_name = createNav)]
+pub fn create_nav(cam: &Camera) -> Nav {
+    Nav::new(cam.size()[1])
+}
+
+impl Nav {
+    // height is the agent's clearance in voxels, rounded up
+    pub fn new(height: f32) -> Nav {
+        Nav { height: (height.ceil() as i32).max(1), clusters: HashMap::new() }
+    }
+    // forget cached graphs around an edited voxel
+    pub fn invalidate(&mut self, x: i32, y: i32, z: i32) {
+        let c = cluster_of([x, y, z]);
+        self.clusters.retain(|k, _| !near(*k, c));
+    }
+    pub fn clear(&mut self) {
+        self.clusters.clear();
+    }
+    pub fn walkable<F: Fn(i32, i32, i32) -> bool>(&self, solid: &F, c: Cell) -> bool {
+        solid(c[0], c[1] - 1, c[2]) && (0..self.height).all(|k| !solid(c[0], c[1] + k, c[2]))
+    }
+    // symmetric: stepping up needs head room above the lower cell
+    pub fn legal<F: Fn(i32, i32, i32) -> bool>(&self, solid: &F, a: Cell, b: Cell) -> bool {
+        if !self.walkable(solid, a) || !self.walkable(solid, b) {
+            return false;
+        }
+        let low = if a[1] < b[1] { a } else { b };
+        a[1] == b[1] || !solid(low[0], low[1] + self.height, low[2])
+    }
+    pub fn moves<F: Fn(i32, i32, i32) -> bool>(&self, solid: &F, a: Cell) -> Vec<Cell> {
+        let mut out = Vec::new();
+        for [dx, dz] in MOVES {
+            for dy in [0, 1, -1] {
+                let b = [a[0] + dx, a[1] + dy, a[2] + dz];
+                if self.legal(solid, a, b) {
+                    out.push(b);
+                }
+            }
+        }
+        out
+    }
+    // nearest walkable cell at or below a world position
+    fn snap<F: Fn(i32, i32, i32) -> bool>(&self, solid: &F, p: [f32; 3]) -> Option<Cell> {
+        let c = [p[0].floor() as i32, p[1].floor() as i32, p[2].floor() as i32];
+        (0..=self.height + 2).map(|k| [c[0], c[1] - k, c[2]]).find(|c| self.walkable(solid, *c))
+    }
+    // A* over cells, optionally confined to one cluster; each move costs 1
+    fn search<F: Fn(i32, i32, i32) -> bool>(&self, solid: &F, from: Cell, to: Cell, within: Option<Cell>) -> Option<Vec<Cell>> {
+        let h = |c: Cell| ((c[0] - to[0]).abs() + (c[2] - to[2]).abs()) as u32;
+        let mut open = BinaryHeap::new();
+        let mut best: HashMap<Cell, u32> = HashMap::new();
+        let mut came: HashMap<Cell, Cell> = HashMap::new();
+        best.insert(from, 0);
+        open.push(Reverse((h(from), 0u32, from)));
+        while let Some(Reverse((_, g, c))) = open.pop() {
+            if c == to {
+                let mut path = vec![c];
+                let mut c = c;
+                while let Some(p) = came.get(&c) {
+                    c = *p;
+                    path.push(c);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if g > best[&c] || best.len() > CELLS * 4 {
+                continue;
+            }
+            for b in self.moves(solid, c) {
+                if within.is_some_and(|w| cluster_of(b) != w) {
+                    continue;
+                }
+                let ng = g + 1;
+                if best.get(&b).is_some_and(|o| *o <= ng) {
+                    continue;
+                }
+                best.insert(b, ng);
+                came.insert(b, c);
+                open.push(Reverse((ng + h(b), ng, b)));
+            }
+        }
+        None
+    }
+    // cost from one cell to every reachable cell of its cluster, indexed like the chunk (x fastest)
+    fn flood<F: Fn(i32, i32, i32) -> bool>(&self, solid: &F, from: Cell) -> Vec<u32> {
+        let cc = cluster_of(from);
+        let n = U::CHUNK;
+        let at = |c: Cell| ((c[0] - cc[0] * n) + ((c[1] - cc[1] * n) + (c[2] - cc[2] * n) * n) * n) as usize;
+        let mut dist = vec![u32::MAX; CELLS];
+        let mut queue = std::collections::VecDeque::new();
+        dist[at(from)] = 0;
+        queue.push_back(from);
+        while let Some(c) = queue.pop_front() {
+            let d = dist[at(c)];
+            for b in self.moves(solid, c) {
+                if cluster_of(b) != cc || dist[at(b)] != u32::MAX {
+                    continue;
+                }
+                dist[at(b)] = d + 1;
+                queue.push_back(b);
+            }
+        }
+        dist
+    }
+    fn cost(dist: &[u32], c: Cell) -> Option<u32> {
+        let n = U::CHUNK;
+        let cc = cluster_of(c);
+        let i = ((c[0] - cc[0] * n) + ((c[1] - cc[1] * n) + (c[2] - cc[2] * n) * n) * n) as usize;
+        Some(dist[i]).filter(|d| *d != u32::MAX)
+    }
+    // occupancy of a chunk plus the margin its moves and clearance checks reach into
+    pub fn sample<F: Fn(i32, i32, i32) -> bool>(&self, solid: &F, cc: Cell) -> impl Fn(i32, i32, i32) -> bool {
+        let n = U::CHUNK;
+        let lo = [cc[0] * n - 1, cc[1] * n - 2, cc[2] * n - 1];
+        let dim = [n + 2, n + self.height + 4, n + 2];
+        let mut bits = vec![false; (dim[0] * dim[1] * dim[2]) as usize];
+        for z in 0..dim[2] {
+            for y in 0..dim[1] {
+                for x in 0..dim[0] {
+                    bits[(x + (y + z * dim[1]) * dim[0]) as usize] = solid(lo[0] + x, lo[1] + y, lo[2] + z);
+                }
+            }
+        }
+        move |x: i32, y: i32, z: i32| {
+            let (x, y, z) = (x - lo[0], y - lo[1], z - lo[2]);
+            if x < 0 || x >= dim[0] || y < 0 || y >= dim[1] || z < 0 || z >= dim[2] {
+                return false;
+            }
+            bits[(x + (y + z * dim[1]) * dim[0]) as usize]
+        }
+    }
+    fn build<F: Fn(i32, i32, i32) -> bool>(&self, solid: &F, cc: Cell) -> Cluster {
+        let solid = &self.sample(solid, cc);
+        let n = U::CHUNK;
+        let lo = [cc[0] * n, cc[1] * n, cc[2] * n];
+        let mut trans: HashMap<Cell, Vec<(Cell, Cell)>> = HashMap::new();
+        for z in 0..n {
+            for y in 0..n {
+                for x in 0..n {
+                    if x > 0 && x < n - 1 && y > 0 && y < n - 1 && z > 0 && z < n - 1 {
+                        continue;
+                    }
+                    let a = [lo[0] + x, lo[1] + y, lo[2] + z];
+                    for b in self.moves(solid, a) {
+                        let cb = cluster_of(b);
+                        if cb != cc {
+                            // canonical order so both clusters see the same pairs
+                            trans.entry(cb).or_default().push(if a < b { (a, b) } else { (b, a) });
+                        }
+                    }
+                }
+            }
+        }
+        let mut out = Cluster::default();
+        let mut nodes = Vec::new();
+        for (_, mut pairs) in trans {
+            pairs.sort();
+            let mut seen = vec![false; pairs.len()];
+            for s in 0..pairs.len() {
+                if seen[s] {
+                    continue;
+                }
+                // contiguous transitions form one entrance, represented by its middle pair
+                seen[s] = true;
+                let mut group = vec![s];
+                let mut k = 0;
+                while k < group.len() {
+                    let p = pairs[group[k]];
+                    for (t, q) in pairs.iter().enumerate() {
+                        if !seen[t] && near(p.0, q.0) && near(p.1, q.1) {
+                            seen[t] = true;
+                            group.push(t);
+                        }
+                    }
+                    k += 1;
+                }
+                group.sort();
+                let (a, b) = pairs[group[group.len() / 2]];
+                let (mine, other) = if cluster_of(a) == cc { (a, b) } else { (b, a) };
+                out.inter.entry(mine).or_default().push(other);
+                nodes.push(mine);
+            }
+        }
+        nodes.sort();
+        nodes.dedup();
+        for a in nodes.iter() {
+            let dist = self.flood(solid, *a);
+            let edges = nodes.iter().filter(|b| *b != a).filter_map(|b| Nav::cost(&dist, *b).map(|d| (*b, d))).collect();
+            out.intra.insert(*a, edges);
+        }
+        out
+    }
+    fn ensure<F: Fn(i32, i32, i32) -> bool>(&mut self, solid: &F, cc: Cell) -> bool {
+        if self.clusters.contains_key(&cc) {
+            return false;
+        }
+        let c = self.build(solid, cc);
+        self.clusters.insert(cc, c);
+        true
+    }
+    // HPA*: search the entrance graph, then refine each hop with a local A* inside its chunk
+    pub fn find<F: Fn(i32, i32, i32) -> bool>(&mut self, solid: &F, from: [f32; 3], to: [f32; 3]) -> Option<Vec<[f32; 3]>> {
+        let s = self.snap(solid, from)?;
+        let g = self.snap(solid, to)?;
+        let (cs, cg) = (cluster_of(s), cluster_of(g));
+        if cs == cg {
+            if let Some(p) = self.search(solid, s, g, Some(cs)) {
+                return Some(self.smooth(solid, &p));
+            }
+        }
+        self.ensure(solid, cs);
+        self.ensure(solid, cg);
+        let ds = self.flood(solid, s);
+        let dg = self.flood(solid, g);
+        let h = |c: Cell| ((c[0] - g[0]).abs() + (c[2] - g[2]).abs()) as u32;
+        let mut open = BinaryHeap::new();
+        let mut best: HashMap<Cell, u32> = HashMap::new();
+        let mut came: HashMap<Cell, Cell> = HashMap::new();
+        let mut built = 0;
+        best.insert(s, 0);
+        open.push(Reverse((h(s), 0u32, s)));
+        while let Some(Reverse((_, cost, c))) = open.pop() {
+            if c == g {
+                break;
+            }
+            if cost > best[&c] {
+                continue;
+            }
+            if best.len() > NODES || built > BUILDS {
+                return None;
+            }
+            let cc = cluster_of(c);
+            if self.ensure(solid, cc) {
+                built += 1;
+            }
+            let mut next: Vec<(Cell, u32)> = Vec::new();
+            if c == s {
+                // s may itself be an entrance, whose crossing is then the way out
+                let cl = &self.clusters[&cs];
+                next.extend(cl.intra.keys().filter_map(|n| Nav::cost(&ds, *n).map(|d| (*n, d))));
+                next.extend(cl.inter.get(&s).into_iter().flatten().map(|b| (*b, 1)));
+            } else {
+                let cl = &self.clusters[&cc];
+                next.extend(cl.intra.get(&c).into_iter().flatten().copied());
+                next.extend(cl.inter.get(&c).into_iter().flatten().map(|b| (*b, 1)));
+            }
+            if cc == cg {
+                if let Some(d) = Nav::cost(&dg, c) {
+                    next.push((g, d));
+                }
+            }
+            for (b, w) in next {
+                let nc = cost + w;
+                if best.get(&b).is_some_and(|o| *o <= nc) {
+                    continue;
+                }
+                best.insert(b, nc);
+                came.insert(b, c);
+                open.push(Reverse((nc + h(b), nc, b)));
+            }
+        }
+        came.get(&g)?;
+        let mut hops = vec![g];
+        let mut c = g;
+        while let Some(p) = came.get(&c) {
+            c = *p;
+            hops.push(c);
+        }
+        hops.reverse();
+        let mut cells = vec![s];
+        for w in hops.windows(2) {
+            let (p, q) = (w[0], w[1]);
+            if cluster_of(p) == cluster_of(q) {
+                let seg = self.search(solid, p, q, Some(cluster_of(p)))?;
+                cells.extend_from_slice(&seg[1..]);
+            } else {
+                cells.push(q);
+            }
+        }
+        Some(self.smooth(solid, &cells))
+    }
+    // walks the xz line between two cells, stepping up or down one voxel where the ground does
+    fn straight<F: Fn(i32, i32, i32) -> bool>(&self, solid: &F, a: Cell, b: Cell) -> bool {
+        let (dx, dz) = (b[0] - a[0], b[2] - a[2]);
+        let (sx, sz) = (dx.signum(), dz.signum());
+        let tdx = if dx != 0 { 1.0 / dx.abs() as f32 } else { f32::INFINITY };
+        let tdz = if dz != 0 { 1.0 / dz.abs() as f32 } else { f32::INFINITY };
+        let (mut tx, mut tz) = (tdx * 0.5, tdz * 0.5);
+        let mut c = a;
+        for _ in 0..dx.abs() + dz.abs() {
+            let mut n = c;
+            if tx < tz {
+                n[0] += sx;
+                tx += tdx;
+            } else {
+                n[2] += sz;
+                tz += tdz;
+            }
+            let Some(y) = [0, 1, -1].into_iter().map(|dy| n[1] + dy).find(|y| self.legal(solid, c, [n[0], *y, n[2]])) else {
+                return false;
+            };
+            c = [n[0], y, n[2]];
+        }
+        c == b
+    }
+    fn smooth<F: Fn(i32, i32, i32) -> bool>(&self, solid: &F, cells: &[Cell]) -> Vec<[f32; 3]> {
+        let at = |c: Cell| [c[0] as f32 + 0.5, c[1] as f32, c[2] as f32 + 0.5];
+        let mut out = vec![at(cells[0])];
+        let mut i = 0;
+        while i + 1 < cells.len() {
+            let mut j = (i + LOOKAHEAD).min(cells.len() - 1);
+            while j > i + 1 && !self.straight(solid, cells[i], cells[j]) {
+                j -= 1;
+            }
+            out.push(at(cells[j]));
+            i = j;
+        }
+        out
+    }
+}
+
+#[wasm_bindgen]
+impl Nav {
+    // flat xyz waypoints (feet height, voxel centres) from one world position to another, or undefined
+    pub fn path(&mut self, regions: &Regions, from: &[f32], to: &[f32]) -> JsValue {
+        if from.len() < 3 || to.len() < 3 {
+            return JsValue::UNDEFINED;
+        }
+        let solid = |x: i32, y: i32, z: i32| regions.voxel(x as f32, y as f32, z as f32) > 0;
+        let Some(p) = self.find(&solid, [from[0], from[1], from[2]], [to[0], to[1], to[2]]) else {
+            return JsValue::UNDEFINED;
+        };
+        let flat: Vec<f32> = p.into_iter().flatten().collect();
+        js_sys::Float32Array::from(flat.as_slice()).into()
+    }
+    #[wasm_bindgen(js_name = invalidate)]
+    pub fn invalidate_of(&mut self, x: i32, y: i32, z: i32) {
+        self.invalidate(x, y, z);
+    }
+    pub fn reset(&mut self) {
+        self.clear();
+    }
+    // walkable surface of chunk (ci, cj, ck) in world chunk coordinates, see web::surface
+    pub fn surface(&self, regions: &Regions, ci: i32, cj: i32, ck: i32) -> js_sys::Object {
+        let solid = |x: i32, y: i32, z: i32| regions.voxel(x as f32, y as f32, z as f32) > 0;
+        W::surface(&surface(self, &solid, [ci, cj, ck]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // floor at y = 0 over two clusters along x, split by a tall wall at x = 16 with a gap at z = 5
+    fn doorway(x: i32, y: i32, z: i32) -> bool {
+        if !(0..32).contains(&x) || !(0..16).contains(&z) {
+            return false;
+        }
+        y == 0 || (x == 16 && z != 5 && (1..=5).contains(&y))
+    }
+
+    #[test]
+    fn path_leaves_through_the_start_entrance() {
+        let mut nav = Nav::new(1.8);
+        let p = nav.find(&doorway, [15.5, 1.0, 5.5], [25.5, 1.0, 10.5]).expect("path through the doorway");
+        assert_eq!(p.first(), Some(&[15.5, 1.0, 5.5]));
+        assert_eq!(p.last(), Some(&[25.5, 1.0, 10.5]));
+        assert!(p.iter().all(|c| nav.walkable(&doorway, [c[0].floor() as i32, c[1] as i32, c[2].floor() as i32])));
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use crate::nav::{ Cell, Nav, cluster_of };
+use crate::utils as U;
+
+// standable cells of one chunk under the same step/headroom rules the path search uses
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct Surface {
+    pub chunk: Cell,
+    // per column (x + z * CHUNK), the world y of every standable cell, lowest first
+    pub cols: Vec<Vec<i32>>,
+    // rectangles of standable cells at one height: x0, z0, x1, z1 (exclusive), y, in world voxels
+    pub polys: Vec<[i32; 5]>,
+    // polygons a single step connects inside the chunk
+    pub edges: Vec<(u32, u32)>,
+    // steps leaving the chunk: standable cell inside, standable cell in the neighbour
+    pub links: Vec<(Cell, Cell)>,
+}
+
+impl Surface {
+    // highest standable y per column, or -1; row-major in z. Only the top layer survives, so a
+    // column walkable both under and on a ledge shows the ledge; cols keeps every layer
+    pub fn grid(&self) -> Vec<i32> {
+        self.cols.iter().map(|c| c.last().copied().unwrap_or(-1)).collect()
+    }
+    // every standable cell as (column, y) pairs, bottom up within a column
+    pub fn layers(&self) -> Vec<i32> {
+        self.cols.iter().enumerate().flat_map(|(c, ys)| ys.iter().flat_map(move |&y| [c as i32, y])).collect()
+    }
+}
+
+pub fn surface<F: Fn(i32, i32, i32) -> bool>(nav: &Nav, solid: &F, cc: Cell) -> Surface {
+    let solid = &nav.sample(solid, cc);
+    let n = U::CHUNK;
+    let lo = [cc[0] * n, cc[1] * n, cc[2] * n];
+    let mut out = Surface { chunk: cc, cols: vec![Vec::new(); (n * n) as usize], ..Default::default() };
+    for z in 0..n {
+        for x in 0..n {
+            for y in 0..n {
+                let c = [lo[0] + x, lo[1] + y, lo[2] + z];
+                if nav.walkable(solid, c) {
+                    out.cols[(x + z * n) as usize].push(c[1]);
+                }
+            }
+        }
+    }
+    // one greedy pass per layer: grow along x, then z
+    let mut owner: HashMap<Cell, u32> = HashMap::new();
+    for y in 0..n {
+        let wy = lo[1] + y;
+        let mut mask: Vec<bool> = out.cols.iter().map(|c| c.contains(&wy)).collect();
+        for z in 0..n {
+            let mut x = 0;
+            while x < n {
+                if !mask[(x + z * n) as usize] {
+                    x += 1;
+                    continue;
+                }
+                let mut w = 1;
+                while x + w < n && mask[(x + w + z * n) as usize] {
+                    w += 1;
+                }
+                let mut d = 1;
+                while z + d < n && (0..w).all(|k| mask[(x + k + (z + d) * n) as usize]) {
+                    d += 1;
+                }
+                let id = out.polys.len() as u32;
+                for dz in 0..d {
+                    for dx in 0..w {
+                        mask[(x + dx + (z + dz) * n) as usize] = false;
+                        owner.insert([lo[0] + x + dx, wy, lo[2] + z + dz], id);
+                    }
+                }
+                out.polys.push([lo[0] + x, lo[2] + z, lo[0] + x + w, lo[2] + z + d, wy]);
+                x += w;
+            }
+        }
+    }
+    let mut cells: Vec<(Cell, u32)> = owner.iter().map(|(c, p)| (*c, *p)).collect();
+    cells.sort();
+    for (a, pa) in cells {
+        for b in nav.moves(solid, a) {
+            if cluster_of(b) != cc {
+                out.links.push((a, b));
+                continue;
+            }
+            let Some(pb) = owner.get(&b).copied() else {
+                continue;
+            };
+            if pa < pb {
+                out.edges.push((pa, pb));
+            }
+        }
+    }
+    out.edges.sort();
+    out.edges.dedup();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // floor at y = 0 reaching one column into the next chunk, raised a step for x >= 8, with a
+    // slab at y = 5 over the 4x4 corner that can be walked both under and on top
+    fn stepped(x: i32, y: i32, z: i32) -> bool {
+        if !(0..=16).contains(&x) || !(0..16).contains(&z) {
+            return false;
+        }
+        y == 0 || (y == 1 && (8..16).contains(&x)) || (y == 5 && x < 4 && z < 4)
+    }
+
+    #[test]
+    fn surface_of_a_step_and_a_slab() {
+        let nav = Nav::new(1.8);
+        let s = surface(&nav, &stepped, [0, 0, 0]);
+        assert_eq!(s.cols[0], vec![1, 6]);
+        assert_eq!(s.grid()[0], 6);
+        assert_eq!(&s.layers()[..4], &[0, 1, 0, 6]);
+        assert_eq!(s.polys, vec![[0, 0, 8, 16, 1], [8, 0, 16, 16, 2], [0, 0, 4, 4, 6]]);
+        // the step joins the two floors; the slab top is out of reach
+        assert_eq!(s.edges, vec![(0, 1)]);
+        assert!(s.links.iter().all(|(a, b)| a[0] == 15 && a[1] == 2 && b[0] == 16 && b[1] == 1));
+        assert_eq!(s.links.iter().filter(|(a, b)| a[2] == b[2]).count(), 16);
+    }
+}
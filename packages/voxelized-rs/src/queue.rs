@@ -1,54 +1,231 @@
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use js_sys::{ Function, Promise, Reflect, Object };
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+// two lanes sharing `limit` running slots; priority > 0 is high, and low work never holds
+// more than `low_limit` of them so a burst of prefetches can't starve what's on screen
+#[derive(Default)]
+pub struct Lanes {
+    limit: usize,
+    low_limit: usize,
+    seq: u64,
+    // id, priority, arrival order
+    pending: Vec<(u32, i32, u64)>,
+    // id -> runs in the high lane
+    running: HashMap<u32, bool>,
+}
+
+impl Lanes {
+    pub fn new(limit: usize, low_limit: usize) -> Lanes {
+        Lanes { limit: limit.max(1), low_limit, ..Default::default() }
+    }
+    pub fn push(&mut self, id: u32, priority: i32) {
+        self.seq += 1;
+        self.pending.push((id, priority, self.seq));
+    }
+    // raises a task's priority; a running task moves to the high lane's count
+    pub fn bump(&mut self, id: u32, priority: i32) -> bool {
+        if let Some(p) = self.pending.iter_mut().find(|p| p.0 == id) {
+            if p.1 >= priority {
+                return false;
+            }
+            p.1 = priority;
+            return true;
+        }
+        match self.running.get_mut(&id) {
+            Some(high) if !*high && priority > 0 => {
+                *high = true;
+                true
+            }
+            _ => false,
+        }
+    }
+    // true when the task was still waiting; a running one just gives its slot back
+    pub fn cancel(&mut self, id: u32) -> bool {
+        if let Some(at) = self.pending.iter().position(|p| p.0 == id) {
+            self.pending.remove(at);
+            return true;
+        }
+        self.running.remove(&id);
+        false
+    }
+    pub fn finish(&mut self, id: u32) {
+        self.running.remove(&id);
+    }
+    // next task allowed to start: highest priority first, then oldest
+    pub fn pop(&mut self) -> Option<(u32, bool)> {
+        if self.running.len() >= self.limit {
+            return None;
+        }
+        let low = self.running.values().filter(|h| !**h).count();
+        let at = self.pending
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.1 > 0 || low < self.low_limit)
+            .max_by(|a, b| a.1.1.cmp(&b.1.1).then(b.1.2.cmp(&a.1.2)))
+            .map(|(at, _)| at)?;
+        let (id, priority, _) = self.pending.remove(at);
+        self.running.insert(id, priority > 0);
+        Some((id, priority > 0))
+    }
+    pub fn queued(&self) -> usize {
+        self.pending.len()
+    }
+    pub fn active(&self) -> usize {
+        self.running.len()
+    }
+}
+
+struct Task {
+    start: Function,
+    resolve: Function,
+    obj: Object,
+}
+
+struct QueueState {
+    lanes: Lanes,
+    tasks: HashMap<u32, Task>,
+    next: u32,
+}
 
 #[wasm_bindgen]
+#[derive(Clone)]
 pub struct Queues {
-    limit: i32,
-    low_limit: i32,
+    st: Rc<RefCell<QueueState>>,
 }
 
 #[wasm_bindgen(js_name = createQueues)]
 pub fn create_queues(limit: i32, low_limit: i32) -> Queues {
-    Queues { limit, low_limit }
+    Queues {
+        st: Rc::new(
+            RefCell::new(QueueState {
+                lanes: Lanes::new(limit.max(1) as usize, low_limit.max(0) as usize),
+                tasks: HashMap::new(),
+                next: 0,
+            })
+        ),
+    }
+}
+
+fn id_of(task: &Object) -> Option<u32> {
+    Reflect::get(task, &"id".into()).ok()?.as_f64().map(|x| x as u32)
+}
+
+impl Queues {
+    fn pump(&self) {
+        loop {
+            let next = self.st.borrow_mut().lanes.pop();
+            let Some((id, high)) = next else {
+                return;
+            };
+            self.launch(id, high);
+        }
+    }
+    fn launch(&self, id: u32, high: bool) {
+        let (start, obj) = {
+            let st = self.st.borrow();
+            let Some(t) = st.tasks.get(&id) else {
+                return;
+            };
+            (t.start.clone(), t.obj.clone())
+        };
+        let _ = Reflect::set(&obj, &"started".into(), &JsValue::TRUE);
+        let _ = Reflect::set(&obj, &"isHigh".into(), &JsValue::from_bool(high));
+        let started = start.call0(&JsValue::NULL).unwrap_or(JsValue::UNDEFINED);
+        let p = match started.dyn_into::<Promise>() {
+            Ok(p) => p,
+            Err(v) => Promise::resolve(&v),
+        };
+        let this = self.clone();
+        let ok = Closure::wrap(
+            Box::new(move |v: JsValue| {
+                this.settle(id, &v);
+            }) as Box<dyn FnMut(JsValue)>
+        );
+        let this = self.clone();
+        let err = Closure::wrap(
+            Box::new(move |_: JsValue| {
+                this.settle(id, &JsValue::UNDEFINED);
+            }) as Box<dyn FnMut(JsValue)>
+        );
+        let _ = p.then2(&ok, &err);
+        ok.forget();
+        err.forget();
+    }
+    // resolves the caller's promise once; later settles of a cancelled task are dropped
+    fn settle(&self, id: u32, v: &JsValue) {
+        let task = {
+            let mut st = self.st.borrow_mut();
+            st.lanes.finish(id);
+            st.tasks.remove(&id)
+        };
+        if let Some(t) = task {
+            let _ = t.resolve.call1(&JsValue::NULL, v);
+        }
+        self.pump();
+    }
 }
 
 #[wasm_bindgen]
 impl Queues {
+    // start() => Promise runs once a slot frees up; returns { promise, task }
     pub fn schedule(&self, start: &Function, priority: i32) -> Object {
         let task = Object::new();
+        let (p, resolve) = new_promise();
+        let id = {
+            let mut st = self.st.borrow_mut();
+            st.next += 1;
+            st.next
+        };
+        let _ = Reflect::set(&task, &"id".into(), &JsValue::from_f64(id as f64));
         let _ = Reflect::set(&task, &"priority".into(), &JsValue::from_f64(priority as f64));
         let _ = Reflect::set(&task, &"started".into(), &JsValue::FALSE);
         let _ = Reflect::set(&task, &"isHigh".into(), &JsValue::from_bool(priority > 0));
-        let (p, resolve) = new_promise();
-        let _ = Reflect::set(&task, &"resolve".into(), &resolve);
-        let started = start.call0(&JsValue::NULL).unwrap_or(JsValue::UNDEFINED);
-        let _ = Reflect::set(&task, &"started".into(), &JsValue::TRUE);
-        let thener = Closure::wrap(
-            Box::new(move |v: JsValue| {
-                let _ = resolve.call1(&JsValue::NULL, &v);
-            }) as Box<dyn FnMut(JsValue)>
-        );
-        if let Some(pr) = started.dyn_ref::<Promise>() {
-            let _ = pr.then(&thener);
-            thener.forget();
+        {
+            let mut st = self.st.borrow_mut();
+            st.tasks.insert(id, Task { start: start.clone(), resolve, obj: task.clone() });
+            st.lanes.push(id, priority);
         }
+        self.pump();
         let o = Object::new();
         let _ = Reflect::set(&o, &"promise".into(), &p);
         let _ = Reflect::set(&o, &"task".into(), &task);
         o
     }
     pub fn bump(&self, task: &Object, priority: i32) {
-        let prev = Reflect::get(&task, &"priority".into())
-            .ok()
-            .and_then(|x| x.as_f64())
-            .unwrap_or(0.0) as i32;
-        if prev >= priority {
+        let Some(id) = id_of(task) else {
+            return;
+        };
+        if !self.st.borrow_mut().lanes.bump(id, priority) {
             return;
         }
-        let _ = Reflect::set(&task, &"priority".into(), &JsValue::from_f64(priority as f64));
-        let is_high = priority > 0;
-        let _ = Reflect::set(&task, &"isHigh".into(), &JsValue::from_bool(is_high));
+        let _ = Reflect::set(task, &"priority".into(), &JsValue::from_f64(priority as f64));
+        let _ = Reflect::set(task, &"isHigh".into(), &JsValue::from_bool(priority > 0));
+        self.pump();
+    }
+    // resolves the task's promise with undefined; a started task frees its slot right away
+    pub fn abort(&self, task: &Object) {
+        let Some(id) = id_of(task) else {
+            return;
+        };
+        let t = {
+            let mut st = self.st.borrow_mut();
+            st.lanes.cancel(id);
+            st.tasks.remove(&id)
+        };
+        if let Some(t) = t {
+            let _ = t.resolve.call1(&JsValue::NULL, &JsValue::UNDEFINED);
+        }
+        self.pump();
+    }
+    pub fn queued(&self) -> u32 {
+        self.st.borrow().lanes.queued() as u32
+    }
+    pub fn active(&self) -> u32 {
+        self.st.borrow().lanes.active() as u32
     }
 }
 
@@ -61,3 +238,67 @@ fn new_promise() -> (Promise, Function) {
     );
     (p, resolve_fn.unwrap())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(l: &mut Lanes) -> Vec<(u32, bool)> {
+        std::iter::from_fn(|| l.pop()).collect()
+    }
+
+    #[test]
+    fn highest_priority_then_oldest_first() {
+        let mut l = Lanes::new(4, 4);
+        l.push(1, 0);
+        l.push(2, 2);
+        l.push(3, 0);
+        l.push(4, 2);
+        assert_eq!(drain(&mut l), vec![(2, true), (4, true), (1, false), (3, false)]);
+        l.push(5, 0);
+        l.push(6, 1);
+        l.push(7, 0);
+        // only raises: a lower bump is ignored
+        assert!(l.bump(7, 3));
+        assert!(!l.bump(6, 0));
+        for id in [1, 2, 3, 4] {
+            l.finish(id);
+        }
+        assert_eq!(drain(&mut l), vec![(7, true), (6, true), (5, false)]);
+    }
+
+    #[test]
+    fn low_lane_never_takes_every_slot() {
+        let mut l = Lanes::new(3, 1);
+        for id in 1..=3 {
+            l.push(id, 0);
+        }
+        assert_eq!(drain(&mut l), vec![(1, false)]);
+        l.push(4, 2);
+        l.push(5, 2);
+        l.push(6, 2);
+        assert_eq!(drain(&mut l), vec![(4, true), (5, true)]);
+        assert_eq!((l.active(), l.queued()), (3, 3));
+        // a running low task bumped to high frees the low lane
+        assert!(l.bump(1, 1));
+        l.finish(4);
+        assert_eq!(drain(&mut l), vec![(6, true)]);
+        l.finish(5);
+        assert_eq!(drain(&mut l), vec![(2, false)]);
+    }
+
+    #[test]
+    fn cancel_drops_waiting_and_frees_running() {
+        let mut l = Lanes::new(1, 1);
+        l.push(1, 1);
+        l.push(2, 1);
+        l.push(3, 1);
+        assert_eq!(l.pop(), Some((1, true)));
+        assert!(l.cancel(2));
+        assert_eq!(l.pop(), None);
+        assert!(!l.cancel(1));
+        assert_eq!(l.active(), 0);
+        assert_eq!(drain(&mut l), vec![(3, true)]);
+        assert!(!l.cancel(9));
+    }
+}
@@ -0,0 +1,116 @@
+use wasm_bindgen::prelude::*;
+
+// first solid voxel along a ray; normal points out of the face that was entered
+// (all zero when the ray starts inside a solid voxel), dist is in voxels from the origin
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Hit {
+    pub voxel: [i32; 3],
+    pub normal: [i32; 3],
+    pub dist: f32,
+    pub value: u8,
+}
+
+impl Hit {
+    pub fn to_js(&self) -> js_sys::Object {
+        let o = js_sys::Object::new();
+        let _ = js_sys::Reflect::set(&o, &"voxel".into(), &js_sys::Int32Array::from(&self.voxel[..]));
+        let _ = js_sys::Reflect::set(&o, &"normal".into(), &js_sys::Int32Array::from(&self.normal[..]));
+        let _ = js_sys::Reflect::set(&o, &"dist".into(), &JsValue::from_f64(self.dist as f64));
+        let _ = js_sys::Reflect::set(&o, &"value".into(), &JsValue::from_f64(self.value as f64));
+        o
+    }
+}
+
+// Amanatides-Woo: step one cell at a time along whichever axis crosses its next boundary first.
+// Nothing non-finite gets in, and no ray of length max crosses more than 3 (max + 1) cells
+pub fn raycast<F: Fn(i32, i32, i32) -> u8>(origin: [f32; 3], dir: [f32; 3], max: f32, solid: F) -> Option<Hit> {
+    let len = (dir[0] * dir[0] + dir[1] * dir[1] + dir[2] * dir[2]).sqrt();
+    if !len.is_finite() || len < 1e-8 || !max.is_finite() || max < 0.0 || origin.iter().any(|o| !o.is_finite()) {
+        return None;
+    }
+    let steps = (max as usize).saturating_add(2).saturating_mul(3);
+    let d = [dir[0] / len, dir[1] / len, dir[2] / len];
+    let mut cell = [origin[0].floor() as i32, origin[1].floor() as i32, origin[2].floor() as i32];
+    let mut step = [0i32; 3];
+    let mut t_max = [f32::INFINITY; 3];
+    let mut t_delta = [f32::INFINITY; 3];
+    for a in 0..3 {
+        if d[a] > 0.0 {
+            step[a] = 1;
+            t_delta[a] = 1.0 / d[a];
+            t_max[a] = ((cell[a] as f32) + 1.0 - origin[a]) * t_delta[a];
+        } else if d[a] < 0.0 {
+            step[a] = -1;
+            t_delta[a] = -1.0 / d[a];
+            t_max[a] = (origin[a] - (cell[a] as f32)) * t_delta[a];
+        }
+    }
+    let mut normal = [0i32; 3];
+    let mut t = 0.0;
+    for _ in 0..steps {
+        let value = solid(cell[0], cell[1], cell[2]);
+        if value > 0 {
+            return Some(Hit { voxel: cell, normal, dist: t, value });
+        }
+        let a = if t_max[0] < t_max[1] {
+            if t_max[0] < t_max[2] { 0 } else { 2 }
+        } else if t_max[1] < t_max[2] {
+            1
+        } else {
+            2
+        };
+        t = t_max[a];
+        if t > max {
+            return None;
+        }
+        cell[a] += step[a];
+        t_max[a] += t_delta[a];
+        normal = [0; 3];
+        normal[a] = -step[a];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(c: [i32; 3]) -> impl Fn(i32, i32, i32) -> u8 {
+        move |x, y, z| ([x, y, z] == c) as u8 * 7
+    }
+
+    #[test]
+    fn axis_aligned_ray_enters_the_near_face() {
+        let h = raycast([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 10.0, at([4, 0, 0])).unwrap();
+        assert_eq!((h.voxel, h.normal, h.value), ([4, 0, 0], [-1, 0, 0], 7));
+        assert!((h.dist - 3.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn diagonal_ray_walks_every_cell_it_crosses() {
+        // y = x - 0.25 crosses x = 2 below y = 2, so (2, 2) is entered from below
+        let h = raycast([0.5, 0.25, 0.5], [1.0, 1.0, 0.0], 10.0, at([2, 2, 0])).unwrap();
+        assert_eq!((h.voxel, h.normal), ([2, 2, 0], [0, -1, 0]));
+        assert!((h.dist - 1.75 * 2f32.sqrt()).abs() < 1e-4);
+        assert_eq!(raycast([0.5, 0.25, 0.5], [1.0, 1.0, 0.0], 10.0, at([2, 1, 0])).map(|h| h.normal), Some([-1, 0, 0]));
+    }
+
+    #[test]
+    fn start_inside_a_solid_voxel_hits_at_zero() {
+        let h = raycast([3.2, 1.5, 0.9], [0.0, -1.0, 0.0], 10.0, at([3, 1, 0])).unwrap();
+        assert_eq!((h.voxel, h.normal, h.dist), ([3, 1, 0], [0; 3], 0.0));
+    }
+
+    #[test]
+    fn misses_beyond_max_and_refuses_what_never_ends() {
+        assert!(raycast([0.5, 0.5, 0.5], [0.0, 0.0, 1.0], 5.0, at([0, 0, 8])).is_none());
+        assert!(raycast([0.5, 0.5, 0.5], [0.0, 0.0, 1.0], 8.0, at([0, 0, 8])).is_some());
+        let never = |_: i32, _: i32, _: i32| 0;
+        assert!(raycast([f32::NAN, 0.0, 0.0], [1.0, 0.0, 0.0], 10.0, never).is_none());
+        assert!(raycast([f32::NEG_INFINITY, 0.0, 0.0], [1.0, 0.0, 0.0], 10.0, never).is_none());
+        assert!(raycast([0.0; 3], [1.0, 0.0, 0.0], f32::INFINITY, never).is_none());
+        assert!(raycast([0.0; 3], [f32::INFINITY, 0.0, 0.0], 10.0, never).is_none());
+        // an empty world still ends once max is spent
+        assert!(raycast([0.5; 3], [1.0, 2.0, 3.0], 1e4, never).is_none());
+    }
+}
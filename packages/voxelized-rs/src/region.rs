@@ -1,26 +1,52 @@
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use js_sys::{ Promise, Reflect, Function, Object, Set, Float32Array };
-use std::collections::HashMap;
-use std::rc::Rc;
-use std::cell::RefCell;
+use js_sys::{ Promise, Function, Object, Set };
+use std::collections::{ BTreeMap, HashMap, HashSet };
+use std::rc::{ Rc, Weak };
+use std::cell::{ Cell, RefCell };
 use crate::utils as U;
-use crate::mesh::{ Mesh };
+use crate::web as W;
+use crate::host::{ Atlas, Clock, Eye, Pixels };
+use crate::mesh::Mesh;
 use crate::chunk::{ Chunk, create_chunk };
+use crate::mesher::{ Meshed, Mesher };
+use crate::bake::Baked;
+use crate::ray::{ Hit, raycast };
+use crate::journal::{ self, Edit, Journal, Voxel };
+use crate::world::World;
+use crate::budget::{ Budget, Stats, Usage };
+use crate::cache::{ Cache, MemoryStore, Store };
+use crate::manifest::Manifest;
+use crate::source::{ Retry, Source };
+use crate::worker::{ self, Bridge };
 use wasm_bindgen::JsValue;
-use web_sys::{ CanvasRenderingContext2d, HtmlImageElement };
+use web_sys::{ AbortController, CanvasRenderingContext2d, HtmlImageElement, Worker };
 
 struct RegionState {
-    img: Option<HtmlImageElement>,
+    img: Option<W::Picture>,
     pending: bool,
+    // why the last fetch gave up; the region then builds as empty until retry()
+    error: Option<String>,
+    // queue handle and promise of the download in flight, so repeat prefetches share it
+    task: Option<(Object, Promise)>,
+    // aborts the download and decode behind task
+    ctrl: Option<AbortController>,
     chunks: HashMap<i32, Chunk>,
     queue: Vec<Chunk>,
     cursor: usize,
-    ctx: Option<CanvasRenderingContext2d>,
+    atlas: Option<Rc<dyn Atlas>>,
     slot: i32,
+    // frame of the last select that wanted this region, for LRU
+    seen: u64,
+    // summed over every chunk, so only recounted after something changed
+    usage: Option<Usage>,
+    journal: Journal,
+    // a loaded diff, keyed by chunk id in chunk-local coordinates, replayed whenever that chunk is filled
+    base: HashMap<i32, Vec<([i32; 3], Voxel)>>,
 }
 
 #[wasm_bindgen]
+#[derive(Clone)]
 pub struct Region {
     pub id: i32,
     pub i: i32,
@@ -28,19 +54,40 @@ pub struct Region {
     pub x: f32,
     pub y: f32,
     pub z: f32,
-    mesh: Rc<RefCell<Mesh>>,
-    queues: JsValue,
+    world: World,
+    mesh: Mesh,
+    queues: Rc<W::Queue>,
+    source: Rc<RefCell<Source>>,
+    retry: Rc<Cell<Retry>>,
+    worker: Rc<RefCell<Option<Bridge>>>,
+    cache: Rc<RefCell<Option<Cache>>>,
+    manifest: Rc<RefCell<Option<Rc<Manifest>>>>,
+    // every region of the Regions this one belongs to, for culling quads across the border
+    neighbours: Weak<RefCell<HashMap<i32, Region>>>,
     st: Rc<RefCell<RegionState>>,
 }
 
-fn make_region(mesh: &JsValue, queues: &JsValue, i: i32, j: i32) -> Region {
-    let (x, y, z) = U::off_of(i, j);
-    let id = U::region_id(i, j);
+pub struct Fetch {
+    pub source: Rc<RefCell<Source>>,
+    pub retry: Rc<Cell<Retry>>,
+    // decodes and meshes url atlases off the main thread when set
+    pub worker: Rc<RefCell<Option<Bridge>>>,
+    // keeps downloaded url atlases across page loads when set
+    pub cache: Rc<RefCell<Option<Cache>>>,
+    // which tiles exist and their hashes, for skipping holes and busting stale urls
+    pub manifest: Rc<RefCell<Option<Rc<Manifest>>>>,
+    pub regions: Weak<RefCell<HashMap<i32, Region>>>,
+}
+
+pub fn make_region(mesh: &Mesh, queues: &Rc<W::Queue>, fetch: &Fetch, world: &World, i: i32, j: i32) -> Region {
+    let (x, y, z) = world.off_of(i, j);
+    let id = world.region_id(i, j);
     let mut chunks = HashMap::new();
     let mut queue = Vec::new();
-    for k in 0..16 {
-        for j2 in 0..16 {
-            for i2 in 0..16 {
+    let n = world.chunks();
+    for k in 0..n {
+        for j2 in 0..n {
+            for i2 in 0..n {
                 let c = create_chunk(i2, j2, k);
                 let c_id = c.id;
                 queue.push(c.clone());
@@ -55,385 +102,1361 @@ fn make_region(mesh: &JsValue, queues: &JsValue, i: i32, j: i32) -> Region {
         x,
         y,
         z,
+        world: *world,
         mesh: mesh.clone(),
         queues: queues.clone(),
+        source: fetch.source.clone(),
+        retry: fetch.retry.clone(),
+        worker: fetch.worker.clone(),
+        cache: fetch.cache.clone(),
+        manifest: fetch.manifest.clone(),
+        neighbours: fetch.regions.clone(),
         st: Rc::new(
             RefCell::new(RegionState {
                 img: None,
                 pending: false,
+                error: None,
+                task: None,
+                ctrl: None,
                 chunks,
                 queue,
                 cursor: 0,
-                ctx: None,
+                atlas: None,
                 slot: -1,
+                seen: 0,
+                usage: None,
+                journal: Journal::default(),
+                base: HashMap::new(),
             })
         ),
     }
 }
 
-#[wasm_bindgen]
 impl Region {
-    #[wasm_bindgen(getter)]
-    pub fn slot(&self) -> i32 {
-        self.st.borrow().slot
-    }
-    #[wasm_bindgen(setter)]
-    pub fn set_slot(&mut self, v: i32) {
-        self.st.borrow_mut().slot = v;
-    }
-    pub fn image(&self, _p: i32) -> Promise {
-        self.prefetch(0)
-    }
-    pub fn chunk(&mut self, ctx: &CanvasRenderingContext2d, index: i32, budget: i32) -> bool {
-        self.st.borrow_mut().ctx = Some(ctx.clone());
-        let start = now();
-        let budget_ms = budget as f64;
-        let checker = || now() - start < budget_ms;
+    pub fn build<C: Clock>(&self, atlas: Rc<dyn Atlas>, clock: &C, index: i32, budget: f64) -> bool {
+        self.st.borrow_mut().atlas = Some(atlas.clone());
+        let start = clock.now();
         loop {
             let mut st = self.st.borrow_mut();
             if st.cursor >= st.queue.len() {
                 break;
             }
-            let mut c = st.queue[st.cursor].clone();
-            st.cursor += 1;
-            if st.img.is_none() {
+            if clock.now() - start >= budget {
                 return false;
             }
+            let c = st.queue[st.cursor].clone();
+            st.cursor += 1;
             drop(st);
-            if !checker() {
-                return false;
-            }
-            c.load(ctx);
-            let merge = Reflect::get(&self.mesh, &"merge".into())
-                .unwrap()
-                .unchecked_into::<Function>();
-            let _ = merge.call2(
-                &self.mesh,
-                &JsValue::from(c.clone()),
-                &JsValue::from_f64(index as f64)
-            );
+            self.load(&c, &*atlas);
+            self.mesh.push(&c, index as u32);
         }
         true
     }
-    pub fn get(&self, ci: i32, cj: i32, ck: i32) -> JsValue {
-        let key = U::chunk_id(ci, cj, ck);
-        let chunk_exists = self.st.borrow().chunks.contains_key(&key);
-        if !chunk_exists {
-            return JsValue::UNDEFINED;
+    pub fn voxel(&self, lx: i32, ly: i32, lz: i32) -> u8 {
+        if !self.world.inside(lx, ly, lz) {
+            return 0;
         }
-        let chunk_ref = {
+        let (chunk, atlas) = {
             let st = self.st.borrow();
-            st.chunks.get(&key).cloned()
+            let key = U::chunk_id(lx / U::CHUNK, ly / U::CHUNK, lz / U::CHUNK);
+            (st.chunks.get(&key).cloned(), st.atlas.clone())
+        };
+        let Some(chunk) = chunk else {
+            return 0;
         };
-        if let Some(chunk) = chunk_ref {
-            return JsValue::from(chunk);
+        let (vx, vy, vz) = (lx % U::CHUNK, ly % U::CHUNK, lz % U::CHUNK);
+        if chunk.voxel(vx, vy, vz).is_none() {
+            if let Some(atlas) = atlas {
+                self.load(&chunk, &*atlas);
+            }
         }
-        JsValue::UNDEFINED
+        chunk.voxel(vx, vy, vz).unwrap_or(0)
     }
-    pub fn dispose(&mut self) -> bool {
+    fn load(&self, chunk: &Chunk, atlas: &dyn Atlas) {
+        if chunk.is_meshed() {
+            return;
+        }
+        self.st.borrow_mut().usage = None;
+        chunk.fill_with(atlas, &self.world, self.mesh.mesher());
+        let edits = self.st.borrow().base.get(&chunk.id).cloned();
+        if let Some(e) = edits {
+            chunk.apply(&e);
+        }
+        self.cull(chunk);
+    }
+    // quad chunks only: drops the faces a neighbouring chunk covers, here or across the border
+    fn cull(&self, chunk: &Chunk) {
+        let (ox, oy, oz) = (chunk.x as i32, chunk.y as i32, chunk.z as i32);
+        chunk.cull(|x, y, z| self.solid(ox + x, oy + y, oz + z));
+    }
+    // occupancy for culling: chunks here are decoded (not meshed) on demand, while other
+    // regions are only read where already loaded, so a border waits for its neighbour
+    fn solid(&self, lx: i32, ly: i32, lz: i32) -> bool {
+        if ly < 0 || ly >= self.world.region {
+            return false;
+        }
+        if !self.world.inside(lx, ly, lz) {
+            return self.across(lx, ly, lz);
+        }
+        let Some(chunk) = self.chunk_at(lx / U::CHUNK, ly / U::CHUNK, lz / U::CHUNK) else {
+            return false;
+        };
+        let (vx, vy, vz) = (lx % U::CHUNK, ly % U::CHUNK, lz % U::CHUNK);
+        if chunk.voxel(vx, vy, vz).is_none() {
+            let atlas = self.st.borrow().atlas.clone();
+            if let Some(atlas) = atlas {
+                if chunk.decode(&*atlas, &self.world, self.mesh.mesher()) {
+                    let edits = self.st.borrow().base.get(&chunk.id).cloned();
+                    if let Some(e) = edits {
+                        chunk.apply(&e);
+                    }
+                }
+            }
+        }
+        chunk.voxel(vx, vy, vz) == Some(1)
+    }
+    fn across(&self, lx: i32, ly: i32, lz: i32) -> bool {
+        let Some(map) = self.neighbours.upgrade() else {
+            return false;
+        };
+        let (wx, wz) = (self.x + lx as f32 + 0.5, self.z + lz as f32 + 0.5);
+        let (i, j) = self.world.pos_of(&[wx, 0.0, wz]);
+        if !self.world.scoped(i, j) {
+            return false;
+        }
+        // already borrowed while a region is being created; its neighbours then count as open
+        let Ok(map) = map.try_borrow() else {
+            return false;
+        };
+        let Some(n) = map.get(&self.world.region_id(i, j)) else {
+            return false;
+        };
+        let (nx, nz) = ((wx - n.x).floor() as i32, (wz - n.z).floor() as i32);
+        let Some(chunk) = n.chunk_at(nx / U::CHUNK, ly / U::CHUNK, nz / U::CHUNK) else {
+            return false;
+        };
+        chunk.voxel(nx % U::CHUNK, ly % U::CHUNK, nz % U::CHUNK) == Some(1)
+    }
+    pub fn set_voxel(&self, lx: i32, ly: i32, lz: i32, rgb: [u8; 3]) -> bool {
+        self.edit(lx, ly, lz, Some(rgb))
+    }
+    pub fn clear_voxel(&self, lx: i32, ly: i32, lz: i32) -> bool {
+        self.edit(lx, ly, lz, None)
+    }
+    fn edit(&self, lx: i32, ly: i32, lz: i32, rgb: Voxel) -> bool {
+        let Some((old, true)) = self.write(lx, ly, lz, rgb) else {
+            return false;
+        };
+        self.st.borrow_mut().journal.record(Edit { at: [lx, ly, lz], old, new: rgb });
+        true
+    }
+    // previous value and whether it changed; None when the voxel can't be written (outside,
+    // or its chunk can't be loaded)
+    fn write(&self, lx: i32, ly: i32, lz: i32, rgb: Voxel) -> Option<(Voxel, bool)> {
+        if !self.world.inside(lx, ly, lz) {
+            return None;
+        }
+        // loads the chunk from the atlas first if it hasn't been touched yet
+        self.voxel(lx, ly, lz);
+        let chunk = self.chunk_at(lx / U::CHUNK, ly / U::CHUNK, lz / U::CHUNK)?;
+        let (vx, vy, vz) = (lx % U::CHUNK, ly % U::CHUNK, lz % U::CHUNK);
+        let old = chunk.get(vx, vy, vz)?;
+        if !chunk.set(vx, vy, vz, rgb) {
+            return Some((old, false));
+        }
+        self.cull(&chunk);
+        self.st.borrow_mut().usage = None;
+        let slot = self.slot_index();
+        if slot >= 0 {
+            self.mesh.patch(&chunk, slot as u32);
+        }
+        if !self.mesh.mesher().reads_neighbours() {
+            return Some((old, true));
+        }
+        for [dx, dy, dz] in [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]] {
+            let (nx, ny, nz) = (lx + dx, ly + dy, lz + dz);
+            if nx / U::CHUNK != lx / U::CHUNK || ny / U::CHUNK != ly / U::CHUNK || nz / U::CHUNK != lz / U::CHUNK {
+                self.touch(nx, ny, nz);
+            }
+        }
+        Some((old, true))
+    }
+    // the cursor only moves once the write went through, so a failed step can be tried again
+    pub fn undo(&self) -> Option<Edit> {
+        let e = self.st.borrow_mut().journal.undo()?;
+        if self.write(e.at[0], e.at[1], e.at[2], e.old).is_none() {
+            self.st.borrow_mut().journal.redo();
+            return None;
+        }
+        Some(e)
+    }
+    pub fn redo(&self) -> Option<Edit> {
+        let e = self.st.borrow_mut().journal.redo()?;
+        if self.write(e.at[0], e.at[1], e.at[2], e.new).is_none() {
+            self.st.borrow_mut().journal.undo();
+            return None;
+        }
+        Some(e)
+    }
+    pub fn can_undo(&self) -> bool {
+        self.st.borrow().journal.can_undo()
+    }
+    pub fn can_redo(&self) -> bool {
+        self.st.borrow().journal.can_redo()
+    }
+    pub fn is_edited(&self) -> bool {
+        let st = self.st.borrow();
+        !st.base.is_empty() || !st.journal.applied().is_empty()
+    }
+    // net change against the base atlas: the loaded diff plus every edit not undone
+    pub fn diff(&self) -> Vec<u8> {
+        let st = self.st.borrow();
+        let mut net = BTreeMap::new();
+        for (id, edits) in st.base.iter() {
+            let o = [id % U::CHUNK, (id / U::CHUNK) % U::CHUNK, id / (U::CHUNK * U::CHUNK)];
+            for (at, v) in edits {
+                net.insert([at[0] + o[0] * U::CHUNK, at[1] + o[1] * U::CHUNK, at[2] + o[2] * U::CHUNK], *v);
+            }
+        }
+        for e in st.journal.applied() {
+            net.insert(e.at, e.new);
+        }
+        journal::encode(&net)
+    }
+    // replaces the replayed diff. Loaded chunks may carry edits the new diff doesn't have, so
+    // they are dropped and refilled from the atlas with the new base: now when there is one,
+    // otherwise on the next build (worker-decoded chunks have no atlas on the page yet)
+    pub fn apply_diff(&self, bytes: &[u8]) -> bool {
+        let Some(edits) = journal::decode(bytes) else {
+            return false;
+        };
+        let mut base: HashMap<i32, Vec<([i32; 3], Voxel)>> = HashMap::new();
+        for ([x, y, z], v) in edits.iter().copied() {
+            let id = U::chunk_id(x / U::CHUNK, y / U::CHUNK, z / U::CHUNK);
+            base.entry(id).or_default().push(([x % U::CHUNK, y % U::CHUNK, z % U::CHUNK], v));
+        }
+        let (loaded, atlas) = {
+            let mut st = self.st.borrow_mut();
+            st.base = base;
+            st.journal.clear();
+            st.usage = None;
+            st.cursor = 0;
+            let loaded: Vec<Chunk> = st.chunks
+                .values()
+                .filter(|c| c.is_meshed())
+                .cloned()
+                .collect();
+            (loaded, st.atlas.clone())
+        };
+        let slot = self.slot_index();
+        for mut chunk in loaded {
+            chunk.dispose();
+            if let Some(atlas) = atlas.as_ref() {
+                self.load(&chunk, &**atlas);
+            }
+            if slot >= 0 {
+                self.mesh.patch(&chunk, slot as u32);
+            }
+        }
+        true
+    }
+    // remeshes the chunk holding a local voxel whose neighbour across the chunk border changed
+    pub fn touch(&self, lx: i32, ly: i32, lz: i32) {
+        if !self.world.inside(lx, ly, lz) {
+            return;
+        }
+        let Some(chunk) = self.chunk_at(lx / U::CHUNK, ly / U::CHUNK, lz / U::CHUNK) else {
+            return;
+        };
+        if !chunk.is_meshed() {
+            return;
+        }
+        chunk.remesh();
+        self.cull(&chunk);
+        self.st.borrow_mut().usage = None;
+        let slot = self.slot_index();
+        if slot >= 0 {
+            self.mesh.patch(&chunk, slot as u32);
+        }
+    }
+    // exposed faces of one chunk, culled against its neighbours here and in loaded regions next door
+    pub fn quads(&self, ci: i32, cj: i32, ck: i32) -> Option<Meshed> {
+        let chunk = self.chunk_at(ci, cj, ck)?;
+        let (ox, oy, oz) = (ci * U::CHUNK, cj * U::CHUNK, ck * U::CHUNK);
+        if chunk.voxel(0, 0, 0).is_none() {
+            let atlas = self.st.borrow().atlas.clone()?;
+            self.load(&chunk, &*atlas);
+        }
+        chunk.quads(|x, y, z| self.solid(ox + x, oy + y, oz + z))
+    }
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+    pub fn chunk_at(&self, ci: i32, cj: i32, ck: i32) -> Option<Chunk> {
+        self.st.borrow().chunks.get(&U::chunk_id(ci, cj, ck)).cloned()
+    }
+    pub fn slot_index(&self) -> i32 {
+        self.st.borrow().slot
+    }
+    pub fn assign_slot(&self, v: i32) {
+        self.st.borrow_mut().slot = v;
+    }
+    pub fn see(&self, frame: u64) {
+        self.st.borrow_mut().seen = frame;
+    }
+    pub fn last_seen(&self) -> u64 {
+        self.st.borrow().seen
+    }
+    pub fn usage(&self) -> Usage {
+        let mut st = self.st.borrow_mut();
+        if let Some(u) = st.usage {
+            return u;
+        }
+        let mut u = Usage::default();
+        for c in st.chunks.values() {
+            u.add(&c.usage());
+        }
+        u.image = st.img.as_ref().map_or(0, |p| p.bytes());
+        // chunk map (one control byte per bucket), build queue and the loaded diff
+        let edit = std::mem::size_of::<([i32; 3], Voxel)>();
+        u.other += st.chunks.capacity() * (std::mem::size_of::<(i32, Chunk)>() + 1);
+        u.other += st.queue.capacity() * std::mem::size_of::<Chunk>();
+        u.other += st.base.values().map(|e| e.capacity() * edit).sum::<usize>();
+        st.usage = Some(u);
+        u
+    }
+    pub fn rewind(&self) {
+        self.st.borrow_mut().cursor = 0;
+    }
+    // an atlas the source already holds in memory, so nothing needs fetching
+    pub fn local(&self) -> Option<Rc<dyn Atlas>> {
+        let px = self.source.borrow().local(self.i, self.j)?;
+        Some(px)
+    }
+    pub fn element(&self) -> Option<W::Picture> {
+        self.st.borrow().img.clone()
+    }
+    pub fn is_fetching(&self) -> bool {
+        let st = self.st.borrow();
+        st.img.is_none() && st.pending
+    }
+    // one download try; failures wait out the backoff and go again until the retry budget
+    // is spent, then mark the region failed. The promise always resolves so the queue moves on.
+    fn attempt(&self, n: u32) -> Promise {
+        let retry = self.retry.get();
+        let Some(signal) = self.st.borrow().ctrl.as_ref().map(|c| c.signal()) else {
+            return Promise::resolve(&JsValue::UNDEFINED);
+        };
+        if signal.aborted() {
+            return Promise::resolve(&JsValue::UNDEFINED);
+        }
+        // this try alone: aborted by its timeout, or with the region's download
+        let Ok(ctrl) = AbortController::new() else {
+            return Promise::resolve(&JsValue::UNDEFINED);
+        };
+        let c = ctrl.clone();
+        W::on_abort(&signal, move || c.abort());
+        let Some(p) = self.download(&ctrl.signal()) else {
+            self.st.borrow_mut().pending = false;
+            return Promise::resolve(&JsValue::UNDEFINED);
+        };
+        let p = W::timeout(p, retry.timeout, &ctrl);
+        Promise::new(&mut |resolve: Function, _reject: Function| {
+            let (this, done) = (self.clone(), resolve.clone());
+            let ok = Closure::wrap(
+                Box::new(move |v: JsValue| {
+                    if let Some(img) = v.dyn_ref::<HtmlImageElement>() {
+                        let mut s = this.st.borrow_mut();
+                        s.img = Some(W::Picture::Image(img.clone()));
+                        s.usage = None;
+                        s.pending = false;
+                    } else if let Some((bmp, baked)) = worker::reply(&v) {
+                        this.adopt(&baked);
+                        let mut s = this.st.borrow_mut();
+                        s.img = Some(W::Picture::Bitmap(bmp));
+                        s.usage = None;
+                        s.pending = false;
+                    } else {
+                        this.fail("loader did not return an image".to_string());
+                    }
+                    let _ = done.call1(&JsValue::NULL, &v);
+                }) as Box<dyn FnMut(JsValue)>
+            );
+            let (this, done, signal) = (self.clone(), resolve.clone(), signal.clone());
+            let err = Closure::wrap(
+                Box::new(move |e: JsValue| {
+                    if signal.aborted() {
+                        let _ = done.call1(&JsValue::NULL, &JsValue::UNDEFINED);
+                        return;
+                    }
+                    if n + 1 >= retry.tries.max(1) {
+                        this.fail(W::reason(&e));
+                        let _ = done.call1(&JsValue::NULL, &JsValue::UNDEFINED);
+                        return;
+                    }
+                    let (this, done) = (this.clone(), done.clone());
+                    let next = Closure::wrap(
+                        Box::new(move |_: JsValue| {
+                            let _ = done.call1(&JsValue::NULL, &this.attempt(n + 1));
+                        }) as Box<dyn FnMut(JsValue)>
+                    );
+                    let _ = W::delay(retry.backoff * 2f64.powi(n as i32)).then(&next);
+                    next.forget();
+                }) as Box<dyn FnMut(JsValue)>
+            );
+            let _ = p.then2(&ok, &err);
+            ok.forget();
+            err.forget();
+        })
+    }
+    // url atlases go through the worker when there is one, everything else loads on the page
+    fn download(&self, signal: &web_sys::AbortSignal) -> Option<Promise> {
+        let source = self.source.borrow();
+        let url = source.url(self.world.zoom, self.i, self.j).map(|u| match self.manifest.borrow().as_ref() {
+            Some(m) => m.stamp(&u, self.i, self.j),
+            None => u,
+        });
+        let cache = self.cache.borrow().clone();
+        if let (Some(cache), Some(url)) = (cache, url.clone()) {
+            let worker = self.worker.borrow().clone();
+            let (n, mesher, signal2) = (self.world.chunks(), self.mesh.mesher(), signal.clone());
+            let p = W::then(&self.cached(&cache, &url, signal), move |v| {
+                let src = v.as_string().unwrap_or_default();
+                let p = match worker {
+                    Some(w) => w.run(&src, n, mesher, &signal2),
+                    None => W::create_image(&src, Some(&signal2)),
+                };
+                let revoke = Closure::once(move || {
+                    let _ = web_sys::Url::revoke_object_url(&src);
+                });
+                let p = p.finally(&revoke);
+                revoke.forget();
+                Ok(p.into())
+            });
+            return Some(p);
+        }
+        if let (Some(w), Some(url)) = (self.worker.borrow().as_ref(), url.as_ref()) {
+            return Some(w.run(url, self.world.chunks(), self.mesh.mesher(), signal));
+        }
+        if let Some(url) = url {
+            return Some(W::create_image(&url, Some(signal)));
+        }
+        source.fetch(self.world.zoom, self.i, self.j, signal)
+    }
+    // promise of a blob: url over the atlas file, from the cache or downloaded into it
+    fn cached(&self, cache: &Cache, url: &str, signal: &web_sys::AbortSignal) -> Promise {
+        let (p, resolve, _) = W::promise_pair();
+        let (z, i, j) = (self.world.zoom, self.i, self.j);
+        let hash = self.manifest
+            .borrow()
+            .as_ref()
+            .and_then(|m| Some(m.entry(i, j)?.hash.clone()))
+            .unwrap_or_default();
+        let (c, url, signal, h) = (cache.clone(), url.to_string(), signal.clone(), hash.clone());
+        cache.load(
+            z,
+            i,
+            j,
+            &hash,
+            Box::new(move |hit| {
+                let bytes = match hit {
+                    Some(b) => Promise::resolve(&JsValue::from(js_sys::Uint8Array::from(b.as_slice()))),
+                    None =>
+                        W::then(&W::fetch_bytes(&url, &signal), move |v| {
+                            c.save(z, i, j, &h, &js_sys::Uint8Array::new(&v).to_vec());
+                            Ok(v)
+                        }),
+                };
+                let src = W::then(&bytes, |v| W::object_url(&js_sys::Uint8Array::new(&v).to_vec()).map(JsValue::from));
+                // resolving with a promise adopts it, rejections included
+                let _ = resolve.call1(&JsValue::NULL, &src);
+            })
+        );
+        p
+    }
+    // chunks meshed by a worker; the loaded diff is replayed on top as load would
+    pub fn adopt(&self, baked: &Baked) {
+        // quads come culled inside the region; one baked before quad mode was switched on is
+        // meshed here as a whole instead
+        let all = self.mesh.mesher() == Mesher::Quad && !baked.quad;
+        let mesher = match (baked.quad || all, baked.tol) {
+            (true, _) => Mesher::Quad,
+            (_, Some(t)) => Mesher::Color(t),
+            _ => Mesher::Box,
+        };
+        let mut adopted = Vec::new();
+        for (id, vox, rgba, m) in baked.split() {
+            let Some(chunk) = self.st.borrow().chunks.get(&id).cloned() else {
+                continue;
+            };
+            chunk.adopt(vox, rgba, mesher, m);
+            let edits = self.st.borrow().base.get(&id).cloned();
+            if let Some(e) = edits {
+                chunk.apply(&e);
+            }
+            adopted.push(chunk);
+        }
+        // what the worker couldn't see: faces against the neighbouring regions, and any a
+        // replayed edit changed on either side of a chunk boundary
+        let last = (self.world.chunks() - 1) * U::CHUNK;
+        let edited: HashSet<i32> = self.st.borrow().base.keys().copied().collect();
+        let touched = |c: &Chunk| {
+            let (i, j, k) = (c.x as i32 / U::CHUNK, c.y as i32 / U::CHUNK, c.z as i32 / U::CHUNK);
+            let near = [(0, 0, 0), (1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0), (0, 0, 1), (0, 0, -1)];
+            near.iter().any(|(a, b, d)| edited.contains(&U::chunk_id(i + a, j + b, k + d)))
+        };
+        for chunk in adopted.iter() {
+            let (x, z) = (chunk.x as i32, chunk.z as i32);
+            let border = x == 0 || z == 0 || x == last || z == last;
+            if mesher == Mesher::Quad && (all || border || touched(chunk)) {
+                self.cull(chunk);
+            }
+        }
+        self.st.borrow_mut().usage = None;
+    }
+    pub fn fetch_error(&self) -> Option<String> {
+        self.st.borrow().error.clone()
+    }
+    pub fn fail(&self, why: String) {
+        let mut st = self.st.borrow_mut();
+        st.error = Some(why);
+        st.pending = false;
+    }
+    pub fn clear(&self) {
+        if self.is_fetching() {
+            self.cancel();
+        }
         let mut st = self.st.borrow_mut();
         for (_, mut chunk) in st.chunks.drain() {
             chunk.dispose();
         }
         st.queue.clear();
-        st.ctx = None;
+        st.atlas = None;
         st.img = None;
+        st.usage = None;
         st.pending = false;
+        st.error = None;
+        st.task = None;
+        st.ctrl = None;
         st.cursor = 0;
+    }
+}
+
+#[wasm_bindgen]
+impl Region {
+    #[wasm_bindgen(getter)]
+    pub fn slot(&self) -> i32 {
+        self.slot_index()
+    }
+    #[wasm_bindgen(setter)]
+    pub fn set_slot(&mut self, v: i32) {
+        self.assign_slot(v);
+    }
+    pub fn image(&self, _p: i32) -> Promise {
+        self.prefetch(0)
+    }
+    pub fn chunk(&mut self, ctx: &CanvasRenderingContext2d, index: i32, budget: i32) -> bool {
+        if let Some(atlas) = self.local() {
+            return self.build(atlas, &W::Now, index, budget as f64);
+        }
+        if self.fetch_error().is_some() {
+            // a missing tile reads as empty space
+            let empty = Rc::new(Pixels { width: 0, data: Vec::new() });
+            return self.build(empty, &W::Now, index, budget as f64);
+        }
+        let waiting = {
+            let st = self.st.borrow();
+            st.img.is_none() && st.cursor < st.queue.len()
+        };
+        if waiting {
+            return false;
+        }
+        self.build(Rc::new(ctx.clone()), &W::Now, index, budget as f64)
+    }
+    pub fn get(&self, ci: i32, cj: i32, ck: i32) -> JsValue {
+        self.chunk_at(ci, cj, ck).map(JsValue::from).unwrap_or(JsValue::UNDEFINED)
+    }
+    #[wasm_bindgen(js_name = setVoxel)]
+    pub fn set_voxel_of(&self, lx: i32, ly: i32, lz: i32, r: u8, g: u8, b: u8) -> bool {
+        self.set_voxel(lx, ly, lz, [r, g, b])
+    }
+    #[wasm_bindgen(js_name = clearVoxel)]
+    pub fn clear_voxel_of(&self, lx: i32, ly: i32, lz: i32) -> bool {
+        self.clear_voxel(lx, ly, lz)
+    }
+    #[wasm_bindgen(js_name = undo)]
+    pub fn undo_of(&self) -> bool {
+        self.undo().is_some()
+    }
+    #[wasm_bindgen(js_name = redo)]
+    pub fn redo_of(&self) -> bool {
+        self.redo().is_some()
+    }
+    #[wasm_bindgen(js_name = diff)]
+    pub fn diff_of(&self) -> js_sys::Uint8Array {
+        js_sys::Uint8Array::from(self.diff().as_slice())
+    }
+    #[wasm_bindgen(js_name = applyDiff)]
+    pub fn apply_diff_of(&self, bytes: &[u8]) -> bool {
+        self.apply_diff(bytes)
+    }
+    #[wasm_bindgen(js_name = quads)]
+    pub fn quads_of(&self, ci: i32, cj: i32, ck: i32) -> JsValue {
+        self.quads(ci, cj, ck).map(|q| q.to_js().into()).unwrap_or(JsValue::UNDEFINED)
+    }
+    pub fn dispose(&mut self) -> bool {
+        self.clear();
         true
     }
     pub fn prefetch(&self, priority: i32) -> Promise {
         if self.st.borrow().img.is_some() {
             let v = self.st.borrow();
-            return Promise::resolve(&JsValue::from(v.img.as_ref().unwrap()));
+            return Promise::resolve(&JsValue::from(v.img.clone().unwrap()));
         }
         {
             let mut st = self.st.borrow_mut();
+            if st.error.is_some() {
+                return Promise::resolve(&JsValue::UNDEFINED);
+            }
+            if let (true, Some((task, p))) = (st.pending, st.task.clone()) {
+                drop(st);
+                self.queues.bump(&task, priority);
+                return p;
+            }
             st.pending = true;
+            st.ctrl = AbortController::new().ok();
         }
-        let url = format!("{}/{}_{}.png", U::ATLAS_URL, self.i, self.j);
-        let queues_obj: &Object = self.queues.unchecked_ref();
-        let st = self.st.clone();
-        let start = Closure::wrap(
-            Box::new(move || {
-                let p = U::create_image(&url);
-                let st2 = st.clone();
-                let then = Closure::wrap(
-                    Box::new(move |v: JsValue| {
-                        let img: HtmlImageElement = v.unchecked_into();
-                        let mut s = st2.borrow_mut();
-                        s.img = Some(img.clone());
-                        s.pending = false;
-                    }) as Box<dyn FnMut(JsValue)>
-                );
-                let _ = p.then(&then);
-                then.forget();
-                p
-            }) as Box<dyn FnMut() -> Promise>
-        );
-        let start_js = start.as_ref().clone();
-        let o: Object = Reflect::get(queues_obj, &"schedule".into())
-            .unwrap()
-            .unchecked_into::<Function>()
-            .call2(&self.queues, &start_js, &JsValue::from_f64(priority as f64))
-            .unwrap()
-            .unchecked_into();
-        start.forget();
-        Reflect::get(&o, &"promise".into()).unwrap().unchecked_into()
-    }
-    pub fn ctx(&self) -> JsValue {
-        self.st
-            .borrow()
-            .ctx.as_ref()
-            .map(|c| JsValue::from(c))
-            .unwrap_or(JsValue::UNDEFINED)
+        let this = self.clone();
+        let Some((task, p)) = self.queues.schedule(Box::new(move || this.attempt(0)), priority) else {
+            let mut st = self.st.borrow_mut();
+            st.pending = false;
+            st.ctrl = None;
+            return Promise::resolve(&JsValue::UNDEFINED);
+        };
+        self.st.borrow_mut().task = Some((task, p.clone()));
+        p
+    }
+    // drops a download that hasn't delivered yet, e.g. the region scrolled out of view
+    pub fn cancel(&self) {
+        let (task, ctrl) = {
+            let mut st = self.st.borrow_mut();
+            if st.img.is_some() || !st.pending {
+                return;
+            }
+            st.pending = false;
+            (st.task.take(), st.ctrl.take())
+        };
+        if let Some(c) = ctrl {
+            c.abort();
+        }
+        if let Some((task, _)) = task {
+            self.queues.abort(&task);
+        }
+    }
+    // clears a failed fetch so the next prefetch downloads again
+    pub fn retry(&self) {
+        self.st.borrow_mut().error = None;
+    }
+    #[wasm_bindgen(getter)]
+    pub fn error(&self) -> Option<String> {
+        self.fetch_error()
     }
     pub fn cursor(&mut self) -> i32 {
-        self.st.borrow_mut().cursor = 0;
+        self.rewind();
         0
     }
     pub fn peek(&self) -> JsValue {
-        self.st
-            .borrow()
-            .img.as_ref()
-            .map(|i| JsValue::from(i))
-            .unwrap_or(JsValue::UNDEFINED)
+        self.element().map(JsValue::from).unwrap_or(JsValue::UNDEFINED)
     }
     pub fn fetching(&self) -> bool {
-        let st = self.st.borrow();
-        st.img.is_none() && st.pending
+        self.is_fetching()
     }
 }
 
 #[wasm_bindgen]
 pub struct Regions {
-    mesh: Rc<RefCell<Mesh>>,
-    cam: JsValue,
-    queues: JsValue,
-    regions: std::cell::RefCell<HashMap<i32, JsValue>>,
+    mesh: Mesh,
+    cam: Box<dyn Eye>,
+    queues: Rc<W::Queue>,
+    fetch: Fetch,
+    world: Cell<World>,
+    regions: Rc<RefCell<HashMap<i32, Region>>>,
+    keep: RefCell<Vec<Region>>,
+    history: RefCell<Journal<i32>>,
+    budget: Cell<Budget>,
+    // most regions kept for drawing at once; Slots::begin sets it to its slot count
+    slots: Cell<usize>,
+    // bumped by every select; regions remember the last one that wanted them
+    frame: Cell<u64>,
+    // regions and bytes evicted so far
+    evicted: Cell<(usize, usize)>,
+    // diffs of evicted (or not yet created) regions, replayed when the region comes back
+    saved: RefCell<HashMap<i32, Vec<u8>>>,
 }
 
+// world is optional: { x0, x1, y0, y1, region, origin, zoom }, see World::from_js
 #[wasm_bindgen(js_name = createRegions)]
-pub fn create_regions(mesh: JsValue, cam: JsValue, q: JsValue) -> Regions {
-    let mesh_rc = Rc::new(RefCell::new(mesh.unchecked_into::<Mesh>()));
-    Regions { mesh: mesh_rc, cam, queues: q, regions: std::cell::RefCell::new(HashMap::new()) }
+pub fn create_regions(mesh: &Mesh, cam: JsValue, q: JsValue, world: JsValue) -> Regions {
+    Regions::new(mesh, Box::new(W::JsEye(cam)), Rc::new(W::JsQueues(q)), World::from_js(&world))
 }
 
-#[wasm_bindgen]
 impl Regions {
-    pub fn vis(&self) -> Set {
-        let mut list: Vec<(i32, i32, f32, i32)> = Vec::new();
-        let pos_val = Reflect::get(&self.cam, &JsValue::from_str("pos")).unwrap();
-        let pos = pos_val.unchecked_into::<Float32Array>();
-        let p = [pos.get_index(0), pos.get_index(1), pos.get_index(2)];
-        let (si, sj) = U::pos_of(&[p[0], p[1], p[2]]);
-        let mut prefetch_near: Vec<JsValue> = Vec::new();
-        {
-            let mut m = self.regions.borrow_mut();
-            let mesh = self.mesh.clone();
-            let queues = self.queues.clone();
-            let ensure = |m: &mut std::collections::HashMap<i32, JsValue>, rx: i32, ry: i32| {
-                let id = U::region_id(rx, ry);
-                if !m.contains_key(&id) {
-                    let r = make_region(&mesh, &queues, rx, ry);
-                    m.insert(id, JsValue::from(r));
+    pub fn new(mesh: &Mesh, cam: Box<dyn Eye>, queues: Rc<W::Queue>, world: World) -> Regions {
+        let regions = Rc::new(RefCell::new(HashMap::new()));
+        Regions {
+            mesh: mesh.clone(),
+            cam,
+            queues,
+            fetch: Fetch {
+                source: Rc::new(RefCell::new(Source::default())),
+                retry: Rc::new(Cell::new(Retry::default())),
+                worker: Rc::new(RefCell::new(None)),
+                cache: Rc::new(RefCell::new(None)),
+                manifest: Rc::new(RefCell::new(None)),
+                regions: Rc::downgrade(&regions),
+            },
+            world: Cell::new(world),
+            regions,
+            keep: RefCell::new(Vec::new()),
+            history: RefCell::new(Journal::default()),
+            budget: Cell::new(Budget::default()),
+            slots: Cell::new(U::SLOT as usize),
+            frame: Cell::new(0),
+            evicted: Cell::new((0, 0)),
+            saved: RefCell::new(HashMap::new()),
+        }
+    }
+    pub fn world(&self) -> World {
+        self.world.get()
+    }
+    // regions fetched before the switch keep the atlas they already have
+    pub fn set_source(&self, s: Source) {
+        *self.fetch.source.borrow_mut() = s;
+    }
+    pub fn insert_pixels(&self, i: i32, j: i32, px: Pixels) {
+        self.fetch.source.borrow_mut().insert(i, j, px);
+    }
+    pub fn set_retry(&self, r: Retry) {
+        self.fetch.retry.set(r);
+    }
+    pub fn set_budget(&self, b: Budget) {
+        self.budget.set(b);
+    }
+    pub fn stats(&self) -> Stats {
+        let map = self.regions.borrow();
+        let mut usage = Usage::default();
+        for r in map.values() {
+            usage.add(&r.usage());
+        }
+        let (evicted, freed) = self.evicted.get();
+        Stats { regions: map.len(), usage, budget: self.budget.get().bytes, evicted, freed }
+    }
+    // a new layout changes what every region id and offset means, so loaded regions and the
+    // edits made under the old one are dropped; prefetch and near are the page's and stay
+    pub fn set_world(&self, w: World) {
+        let cur = self.world.get();
+        let w = World { prefetch: cur.prefetch, near: cur.near, ..w.fixed() };
+        if w == cur {
+            return;
+        }
+        self.world.set(w);
+        for (_, r) in self.regions.borrow_mut().drain() {
+            r.clear();
+        }
+        self.keep.borrow_mut().clear();
+        self.saved.borrow_mut().clear();
+        *self.history.borrow_mut() = Journal::default();
+    }
+    // tiles missing from it are never created or fetched, the cache follows its version and
+    // the world it carries (if any) replaces the current one
+    pub fn set_manifest(&self, m: Manifest) {
+        if let Some(w) = m.world {
+            self.set_world(w);
+        }
+        *self.fetch.manifest.borrow_mut() = Some(Rc::new(m));
+        let cache = self.fetch.cache.borrow().clone();
+        self.set_cache(cache);
+    }
+    // true without a manifest
+    pub fn listed(&self, i: i32, j: i32) -> bool {
+        self.fetch.manifest.borrow().as_ref().is_none_or(|m| m.has(i, j, self.world.get().zoom))
+    }
+    // entries from other dataset versions are purged; a manifest's version wins over the
+    // cache's own. None stops caching
+    pub fn set_cache(&self, c: Option<Cache>) {
+        let c = c.map(|c| match self.fetch.manifest.borrow().as_ref() {
+            Some(m) => c.with_version(&m.version),
+            None => c,
+        });
+        if let Some(c) = c.as_ref() {
+            c.purge();
+        }
+        *self.fetch.cache.borrow_mut() = c;
+    }
+    // None goes back to decoding on the main thread
+    pub fn set_worker(&self, w: Option<Bridge>) {
+        *self.fetch.worker.borrow_mut() = w;
+    }
+    pub fn ensure(&self, i: i32, j: i32) -> Region {
+        let id = self.world.get().region_id(i, j);
+        self.regions
+            .borrow_mut()
+            .entry(id)
+            .or_insert_with(|| {
+                let r = make_region(&self.mesh, &self.queues, &self.fetch, &self.world.get(), i, j);
+                if let Some(bytes) = self.saved.borrow_mut().remove(&id) {
+                    r.apply_diff(&bytes);
                 }
-                id
-            };
-            let mvp_val = Reflect::get(&self.cam, &JsValue::from_str("MVP")).unwrap();
-            let vp = mvp_val.unchecked_into::<Float32Array>();
-            let is_ready_fn: Function = Reflect::get(&self.mesh, &JsValue::from_str("isReady"))
-                .unwrap()
-                .unchecked_into();
-            let mesh_ready = is_ready_fn.call0(&self.mesh).unwrap().as_bool().unwrap_or(false);
-            for di in 0..U::PREFETCH * 2 {
-                for dj in 0..U::PREFETCH * 2 {
-                    let mut i = di - U::PREFETCH;
-                    let mut j = dj - U::PREFETCH;
-                    if i == 0 && j == 0 {
-                        let id = ensure(&mut m, si, sj);
-                        list.push((si, sj, -1.0, id));
-                        continue;
-                    }
-                    let d = ((i * i + j * j) as f32).sqrt();
-                    i += si;
-                    j += sj;
-                    let (x, y, z) = U::off_of(i, j);
-                    if !U::culling(&vp, x, y, z) && d > (U::SLOT as f32) {
-                        continue;
-                    }
-                    if !U::scoped(i, j) {
-                        continue;
-                    }
-                    let id = ensure(&mut m, i, j);
-                    let r = m.get(&id).unwrap();
-                    if d <= (U::SLOT as f32) {
-                        if mesh_ready {
-                            prefetch_near.push(r.clone());
-                        }
-                    }
-                    if !U::culling(&vp, x, y, z) {
-                        continue;
+                r
+            })
+            .clone()
+    }
+    pub fn region(&self, id: i32) -> Option<Region> {
+        self.regions.borrow().get(&id).cloned()
+    }
+    pub fn keep(&self) -> Vec<Region> {
+        self.keep.borrow().clone()
+    }
+    pub fn len(&self) -> usize {
+        self.regions.borrow().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.regions.borrow().is_empty()
+    }
+    pub fn set_slots(&self, n: usize) {
+        self.slots.set(n.max(1));
+    }
+    // nearest visible regions (at most slots) and regions close enough to prefetch
+    pub fn select(&self, pos: [f32; 3], vp: &[f32; 16], near: bool) -> (Vec<Region>, Vec<Region>) {
+        let mut list: Vec<(i32, i32, f32)> = Vec::new();
+        let mut prefetch_near: Vec<Region> = Vec::new();
+        let frame = self.frame.get() + 1;
+        self.frame.set(frame);
+        let world = self.world.get();
+        let (si, sj) = world.pos_of(&pos);
+        let (reach, close) = (world.prefetch, world.near as f32);
+        for di in 0..reach * 2 {
+            for dj in 0..reach * 2 {
+                let mut i = di - reach;
+                let mut j = dj - reach;
+                if i == 0 && j == 0 {
+                    if self.listed(si, sj) {
+                        self.ensure(si, sj).see(frame);
+                        list.push((si, sj, -1.0));
                     }
-                    list.push((i, j, d, id));
+                    continue;
                 }
+                let d = ((i * i + j * j) as f32).sqrt();
+                i += si;
+                j += sj;
+                let (x, y, z) = world.off_of(i, j);
+                let visible = world.culling(vp, x, y, z);
+                if !visible && d > close {
+                    continue;
+                }
+                if !world.scoped(i, j) || !self.listed(i, j) {
+                    continue;
+                }
+                let r = self.ensure(i, j);
+                r.see(frame);
+                if d <= close && near {
+                    prefetch_near.push(r);
+                }
+                if !visible {
+                    continue;
+                }
+                list.push((i, j, d));
             }
         }
         list.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
-        let mut keep = Vec::new();
-        for (i, j, d, id) in list.into_iter() {
-            if !U::scoped(i, j) {
+        let keep: Vec<Region> = list
+            .into_iter()
+            .filter(|(i, j, _)| world.scoped(*i, *j))
+            .take(self.slots.get())
+            .map(|(i, j, _)| self.ensure(i, j))
+            .collect();
+        *self.keep.borrow_mut() = keep.clone();
+        (keep, prefetch_near)
+    }
+    // drop inactive regions, far and long unseen first, until the rest fit the budget
+    pub fn evict(&self, active: &HashSet<i32>, oi: i32, oj: i32) -> Vec<Region> {
+        let budget = self.budget.get();
+        let frame = self.frame.get();
+        let mut map = self.regions.borrow_mut();
+        let mut total = 0;
+        let mut to_remove: Vec<(f32, i32, usize)> = Vec::new();
+        for (id, r) in map.iter() {
+            let bytes = r.usage().total();
+            total += bytes;
+            if active.contains(id) {
                 continue;
             }
-            keep.push((i, j, d, id));
-            if keep.len() >= (U::SLOT as usize) {
+            let dist = ((r.i - oi) as f32).hypot((r.j - oj) as f32);
+            to_remove.push((budget.score(dist, frame.saturating_sub(r.last_seen())), *id, bytes));
+        }
+        if !budget.over(map.len(), total) {
+            return Vec::new();
+        }
+        to_remove.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        let (mut count, mut freed) = self.evicted.get();
+        let mut out = Vec::new();
+        for (_, rid, bytes) in to_remove.into_iter() {
+            if !budget.over(map.len(), total) {
                 break;
             }
-        }
-        let keep_set = Set::new(&JsValue::undefined());
-        {
-            let m = self.regions.borrow();
-            for (_, _, _, id) in &keep {
-                let r = m.get(id).unwrap();
-                let pf: Function = Reflect::get(r, &JsValue::from_str("prefetch"))
-                    .unwrap()
-                    .unchecked_into();
-                let _ = pf.call1(r, &JsValue::from_f64(2.0));
-                let _ = keep_set.add(r);
+            if let Some(r) = map.remove(&rid) {
+                if r.is_edited() {
+                    self.saved.borrow_mut().insert(rid, r.diff());
+                }
+                r.clear();
+                total -= bytes;
+                count += 1;
+                freed += bytes;
+                out.push(r);
             }
         }
-        let active = Set::new(&JsValue::undefined());
-        if let Ok(Some(iter)) = js_sys::try_iter(&keep_set) {
-            for v in iter {
-                let r = v.unwrap();
-                let _ = active.add(&r);
-            }
+        self.evicted.set((count, freed));
+        out
+    }
+    pub fn voxel(&self, wx: f32, wy: f32, wz: f32) -> u8 {
+        let world = self.world.get();
+        let (ri, rj) = world.pos_of(&[wx, wy, wz]);
+        if !world.scoped(ri, rj) {
+            return 0;
         }
-        for r in prefetch_near.into_iter() {
-            let _ = active.add(&r);
-            let fetching: Function = Reflect::get(&r, &JsValue::from_str("fetching"))
-                .unwrap()
-                .unchecked_into();
-            let is_fetching = fetching.call0(&r).unwrap().as_bool().unwrap_or(false);
-            if is_fetching {
+        let Some(r) = self.region(world.region_id(ri, rj)) else {
+            return 0;
+        };
+        let lx = (wx - r.x).floor() as i32;
+        let ly = (wy - r.y).floor() as i32;
+        let lz = (wz - r.z).floor() as i32;
+        r.voxel(lx, ly, lz)
+    }
+    pub fn set_voxel(&self, wx: f32, wy: f32, wz: f32, rgb: [u8; 3]) -> bool {
+        self.edit(wx, wy, wz, Some(rgb))
+    }
+    pub fn clear_voxel(&self, wx: f32, wy: f32, wz: f32) -> bool {
+        self.edit(wx, wy, wz, None)
+    }
+    fn edit(&self, wx: f32, wy: f32, wz: f32, rgb: Voxel) -> bool {
+        let world = self.world.get();
+        let (ri, rj) = world.pos_of(&[wx, wy, wz]);
+        if !world.scoped(ri, rj) {
+            return false;
+        }
+        let Some(r) = self.region(world.region_id(ri, rj)) else {
+            return false;
+        };
+        let (lx, ly, lz) = ((wx - r.x).floor() as i32, (wy - r.y).floor() as i32, (wz - r.z).floor() as i32);
+        let done = match rgb {
+            Some(c) => r.set_voxel(lx, ly, lz, c),
+            None => r.clear_voxel(lx, ly, lz),
+        };
+        if !done {
+            return false;
+        }
+        self.history.borrow_mut().record(r.id);
+        self.border(&r, [lx, ly, lz]);
+        true
+    }
+    // chunks across a region border belong to another Region
+    fn border(&self, r: &Region, at: [i32; 3]) {
+        if !self.mesh.mesher().reads_neighbours() {
+            return;
+        }
+        let world = self.world.get();
+        let (wx, wz) = (r.x + at[0] as f32 + 0.5, r.z + at[2] as f32 + 0.5);
+        for [dx, dz] in [[1.0, 0.0], [-1.0, 0.0], [0.0, 1.0], [0.0, -1.0]] {
+            let (ni, nj) = world.pos_of(&[wx + dx, 0.0, wz + dz]);
+            if (ni, nj) == (r.i, r.j) || !world.scoped(ni, nj) {
                 continue;
             }
-            let pf: Function = Reflect::get(&r, &JsValue::from_str("prefetch"))
-                .unwrap()
-                .unchecked_into();
-            let _ = pf.call1(&r, &JsValue::from_f64(0.0));
-        }
-        if let Some((oi, oj, _, _)) = keep.get(0).cloned() {
-            let current_size = self.regions.borrow().len();
-            if current_size <= (U::CACHE as usize) {
-                return keep_set;
+            if let Some(n) = self.region(world.region_id(ni, nj)) {
+                n.touch((wx + dx - n.x).floor() as i32, at[1], (wz + dz - n.z).floor() as i32);
             }
-            let mut to_remove: Vec<(f32, i32)> = Vec::new();
-            {
-                let map = self.regions.borrow();
-                for (id, r) in map.iter() {
-                    if active.has(r) {
-                        continue;
-                    }
-                    let ri = Reflect::get(r, &JsValue::from_str("i"))
-                        .unwrap()
-                        .as_f64()
-                        .unwrap_or(0.0) as i32;
-                    let rj = Reflect::get(r, &JsValue::from_str("j"))
-                        .unwrap()
-                        .as_f64()
-                        .unwrap_or(0.0) as i32;
-                    let da = ((ri - oi) as f32).hypot((rj - oj) as f32);
-                    to_remove.push((da, *id));
-                }
-            }
-            to_remove.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
-            let mut map = self.regions.borrow_mut();
-            for (_, rid) in to_remove.into_iter() {
-                if map.len() <= (U::CACHE as usize) {
-                    break;
-                }
-                if let Some(r) = map.remove(&rid) {
-                    let disp: Function = Reflect::get(&r, &JsValue::from_str("dispose"))
-                        .unwrap()
-                        .unchecked_into();
-                    let _ = disp.call0(&r);
-                }
+        }
+    }
+    // steps back through edits in the order they were made, across regions; evicted regions
+    // keep their net diff but not their history, so their entries are skipped. A step whose
+    // region can't write right now leaves both cursors where they were
+    pub fn undo(&self) -> Option<Edit> {
+        loop {
+            let id = self.history.borrow_mut().undo()?;
+            let Some(r) = self.region(id) else {
+                continue;
+            };
+            if !r.can_undo() {
+                continue;
             }
+            let Some(e) = r.undo() else {
+                self.history.borrow_mut().redo();
+                return None;
+            };
+            self.border(&r, e.at);
+            return Some(e);
         }
-        keep_set
     }
-    pub fn pick(&self, wx: f32, wy: f32, wz: f32) -> i32 {
-        let (rxi, ryj) = (
-            U::SCOPE_X0 + ((wx / (U::REGION as f32)).floor() as i32),
-            U::SCOPE_Y1 - ((wz / (U::REGION as f32)).floor() as i32),
-        );
-        if rxi < U::SCOPE_X0 || rxi > U::SCOPE_X1 || ryj < U::SCOPE_Y0 || ryj > U::SCOPE_Y1 {
-            return 0;
+    pub fn redo(&self) -> Option<Edit> {
+        loop {
+            let id = self.history.borrow_mut().redo()?;
+            let Some(r) = self.region(id) else {
+                continue;
+            };
+            if !r.can_redo() {
+                continue;
+            }
+            let Some(e) = r.redo() else {
+                self.history.borrow_mut().undo();
+                return None;
+            };
+            self.border(&r, e.at);
+            return Some(e);
         }
-        let id = U::region_id(rxi, ryj);
-        let m = self.regions.borrow();
-        let r = m.get(&id);
-        if r.is_none() {
-            return 0;
+    }
+    pub fn diff(&self, i: i32, j: i32) -> Option<Vec<u8>> {
+        let id = self.world.get().region_id(i, j);
+        match self.region(id) {
+            Some(r) => r.is_edited().then(|| r.diff()),
+            None => self.saved.borrow().get(&id).cloned(),
         }
-        let r = r.unwrap();
-        let x = Reflect::get(r, &JsValue::from_str("x")).unwrap().as_f64().unwrap_or(0.0) as f32;
-        let y = Reflect::get(r, &JsValue::from_str("y")).unwrap().as_f64().unwrap_or(0.0) as f32;
-        let z = Reflect::get(r, &JsValue::from_str("z")).unwrap().as_f64().unwrap_or(0.0) as f32;
-        let lx = wx - x;
-        let ly = wy - y;
-        let lz = wz - z;
-        let ci = (lx / (U::CHUNK as f32)).floor() as i32;
-        let cj = (ly / (U::CHUNK as f32)).floor() as i32;
-        let ck = (lz / (U::CHUNK as f32)).floor() as i32;
-        if ci < 0 || ci > 15 || cj < 0 || cj > 15 || ck < 0 || ck > 15 {
-            return 0;
+    }
+    pub fn apply_diff(&self, i: i32, j: i32, bytes: &[u8]) -> bool {
+        let id = self.world.get().region_id(i, j);
+        match self.region(id) {
+            Some(r) => r.apply_diff(bytes),
+            None => {
+                if journal::decode(bytes).is_none() {
+                    return false;
+                }
+                self.saved.borrow_mut().insert(id, bytes.to_vec());
+                true
+            }
         }
-        let get_fn: Function = Reflect::get(r, &JsValue::from_str("get")).unwrap().unchecked_into();
-        let chunk_js = get_fn
-            .call3(
-                r,
-                &JsValue::from_f64(ci as f64),
-                &JsValue::from_f64(cj as f64),
-                &JsValue::from_f64(ck as f64)
-            )
-            .unwrap();
-        if chunk_js.is_undefined() {
-            return 0;
+    }
+    // max is clamped to the world span so an unbounded ray over empty space still terminates
+    pub fn raycast(&self, origin: [f32; 3], dir: [f32; 3], max: f32) -> Option<Hit> {
+        let world = self.world.get();
+        let span = world.span() + world.region as f32;
+        raycast(origin, dir, max.min(span), |x, y, z| self.voxel(x as f32, y as f32, z as f32))
+    }
+}
+
+#[wasm_bindgen]
+impl Regions {
+    pub fn vis(&self) -> Set {
+        let (keep, near) = self.select(self.cam.pos(), &self.cam.mvp(), self.mesh.ready());
+        let keep_set = Set::new(&JsValue::undefined());
+        let mut active = HashSet::new();
+        for r in &keep {
+            let _ = r.prefetch(2);
+            let _ = keep_set.add(&JsValue::from(r.clone()));
+            active.insert(r.id);
         }
-        let vox_fn: Function = Reflect::get(&chunk_js, &JsValue::from_str("vox"))
-            .unwrap()
-            .unchecked_into();
-        let mut vox_data = vox_fn.call0(&chunk_js).unwrap();
-        if vox_data.is_undefined() {
-            let ctx_fn = Reflect::get(r, &JsValue::from_str("ctx"))
-                .unwrap()
-                .unchecked_into::<Function>();
-            let ctx = ctx_fn.call0(r).unwrap();
-            if !ctx.is_undefined() {
-                let load_fn: Function = Reflect::get(&chunk_js, &JsValue::from_str("load"))
-                    .unwrap()
-                    .unchecked_into();
-                let _ = load_fn.call1(&chunk_js, &ctx);
-                vox_data = vox_fn.call0(&chunk_js).unwrap();
+        for r in near.iter() {
+            active.insert(r.id);
+            if r.is_fetching() {
+                continue;
             }
+            let _ = r.prefetch(0);
         }
-        if vox_data.is_undefined() {
-            return 0;
+        // queued downloads for regions that left the view would only delay the ones in it
+        let gone: Vec<Region> = self.regions
+            .borrow()
+            .values()
+            .filter(|r| !active.contains(&r.id) && r.is_fetching())
+            .cloned()
+            .collect();
+        for r in gone {
+            r.cancel();
         }
-        let vx = (lx - (ci as f32) * (U::CHUNK as f32)).floor() as i32;
-        let vy = (ly - (cj as f32) * (U::CHUNK as f32)).floor() as i32;
-        let vz = (lz - (ck as f32) * (U::CHUNK as f32)).floor() as i32;
-        if vx < 0 || vx > 15 || vy < 0 || vy > 15 || vz < 0 || vz > 15 {
-            return 0;
+        if let Some(o) = keep.first() {
+            self.evict(&active, o.i, o.j);
+        }
+        keep_set
+    }
+    // template with {z} {i} {j} {ext}, e.g. "https://host/atlas/v1/{z}_{i}_{j}.{ext}"
+    #[wasm_bindgen(js_name = atlasUrl)]
+    pub fn atlas_url(&self, template: &str, ext: Option<String>) {
+        let ext = ext.unwrap_or_else(|| "png".to_string());
+        self.set_source(Source::Url { template: template.to_string(), ext });
+    }
+    // loader(i, j, z) returns a Promise of an HTMLImageElement or of a URL
+    #[wasm_bindgen(js_name = atlasLoader)]
+    pub fn atlas_loader(&self, loader: Function) {
+        self.set_source(Source::Loader(loader));
+    }
+    // RGBA rows of a decoded atlas, width pixels wide
+    #[wasm_bindgen(js_name = atlasPixels)]
+    pub fn atlas_pixels(&self, i: i32, j: i32, width: u32, data: Vec<u8>) {
+        self.insert_pixels(i, j, Pixels { width, data });
+    }
+    // tries per atlas, first backoff and per-try timeout in milliseconds (0 disables the timeout)
+    pub fn retries(&self, tries: u32, backoff: f64, timeout: f64) {
+        self.set_retry(Retry { tries, backoff, timeout });
+    }
+    // a worker that called serveAtlas(); url atlases are then fetched, decoded and meshed there
+    #[wasm_bindgen(js_name = useWorker)]
+    pub fn use_worker(&self, worker: Worker) {
+        self.set_worker(Some(Bridge::new(worker)));
+    }
+    // how many regions to keep visible; SLOT until a Slots begins with this, which sets its own count
+    #[wasm_bindgen(js_name = slots)]
+    pub fn slots_of(&self, n: u32) {
+        self.set_slots(n as usize);
+    }
+    // a parsed manifest (see Manifest); false when it doesn't read as one
+    #[wasm_bindgen(js_name = manifest)]
+    pub fn manifest_of(&self, m: JsValue) -> bool {
+        let Some(m) = Manifest::from_js(m) else {
+            return false;
+        };
+        self.set_manifest(m);
+        true
+    }
+    // keeps downloaded atlases in store ({ get, put, delete, keys }, see JsStore) under version;
+    // without a store they are only kept in memory for this page
+    pub fn persist(&self, store: JsValue, version: &str) {
+        let store: Rc<dyn Store> = match store.dyn_into::<Object>() {
+            Ok(o) => Rc::new(W::JsStore(o)),
+            Err(_) => Rc::new(MemoryStore::default()),
+        };
+        self.set_cache(Some(Cache::new(store, version)));
+    }
+    // bytes regions may hold before eviction (Infinity for no limit); age is how many regions
+    // of distance one frame unseen counts for when picking what to drop (default 1/60);
+    // regions caps their number as well, unlimited unless given
+    pub fn budget(&self, bytes: f64, age: Option<f32>, regions: Option<u32>) {
+        let d = Budget::default();
+        let bytes = if bytes.is_finite() { bytes.max(0.0) as usize } else { usize::MAX };
+        let regions = regions.map_or(usize::MAX, |n| n as usize);
+        self.set_budget(Budget { bytes, regions, age: age.unwrap_or(d.age) });
+    }
+    // { regions, bytes, voxels, meshes, image, other, budget, evicted, freed }
+    #[wasm_bindgen(getter, js_name = stats)]
+    pub fn stats_of(&self) -> Object {
+        self.stats().to_js()
+    }
+    #[wasm_bindgen(getter, js_name = world)]
+    pub fn world_of(&self) -> Object {
+        self.world.get().to_js()
+    }
+    pub fn pick(&self, wx: f32, wy: f32, wz: f32) -> i32 {
+        self.voxel(wx, wy, wz) as i32
+    }
+    #[wasm_bindgen(js_name = setVoxel)]
+    pub fn set_voxel_of(&self, wx: f32, wy: f32, wz: f32, r: u8, g: u8, b: u8) -> bool {
+        self.set_voxel(wx, wy, wz, [r, g, b])
+    }
+    #[wasm_bindgen(js_name = clearVoxel)]
+    pub fn clear_voxel_of(&self, wx: f32, wy: f32, wz: f32) -> bool {
+        self.clear_voxel(wx, wy, wz)
+    }
+    #[wasm_bindgen(js_name = undo)]
+    pub fn undo_of(&self) -> bool {
+        self.undo().is_some()
+    }
+    #[wasm_bindgen(js_name = redo)]
+    pub fn redo_of(&self) -> bool {
+        self.redo().is_some()
+    }
+    // Uint8Array of the region's net edits, or undefined when it has none
+    #[wasm_bindgen(js_name = diff)]
+    pub fn diff_of(&self, i: i32, j: i32) -> JsValue {
+        self.diff(i, j).map(|d| js_sys::Uint8Array::from(d.as_slice()).into()).unwrap_or(JsValue::UNDEFINED)
+    }
+    #[wasm_bindgen(js_name = applyDiff)]
+    pub fn apply_diff_of(&self, i: i32, j: i32, bytes: &[u8]) -> bool {
+        self.apply_diff(i, j, bytes)
+    }
+    // { voxel, normal, dist, value } of the first solid voxel, or undefined
+    #[wasm_bindgen(js_name = raycast)]
+    pub fn raycast_of(&self, origin: &[f32], dir: &[f32], max: f32) -> JsValue {
+        if origin.len() < 3 || dir.len() < 3 {
+            return JsValue::UNDEFINED;
         }
-        let idx = (vx + (vy + vz * U::CHUNK) * U::CHUNK) as u32;
-        let arr = js_sys::Uint8Array::from(vox_data);
-        arr.get_index(idx) as i32
+        let o = [origin[0], origin[1], origin[2]];
+        let d = [dir[0], dir[1], dir[2]];
+        self.raycast(o, d, max).map(|h| h.to_js().into()).unwrap_or(JsValue::UNDEFINED)
     }
 }
 
-fn now() -> f64 {
-    web_sys::window().unwrap().performance().unwrap().now()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::Scheduler;
+    use crate::mesh::create_mesh;
+
+    // a camera that never moves and a queue that never runs, so nothing reaches for JS
+    struct Still;
+
+    impl Eye for Still {
+        fn pos(&self) -> [f32; 3] {
+            [0.0; 3]
+        }
+        fn mvp(&self) -> [f32; 16] {
+            ALL
+        }
+    }
+
+    struct Idle;
+
+    impl Scheduler for Idle {
+        type Task = Object;
+        type Done = Promise;
+        fn schedule(&self, _: Box<dyn FnMut() -> Promise>, _: i32) -> Option<(Object, Promise)> {
+            None
+        }
+        fn bump(&self, _: &Object, _: i32) {}
+        fn abort(&self, _: &Object) {}
+    }
+
+    // a view matrix every sphere passes
+    const ALL: [f32; 16] = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+    fn regions(mesh: &Mesh, world: World) -> Regions {
+        Regions::new(mesh, Box::new(Still), Rc::new(Idle), world)
+    }
+
+    // a one-chunk world whose region is built from an empty atlas
+    fn tiny() -> (Regions, Region) {
+        let world = World { region: U::CHUNK, ..World::new(0, 0, 0, 0) };
+        let regions = regions(&create_mesh(), world);
+        let r = regions.ensure(0, 0);
+        let (w, h) = world.atlas();
+        let px = Pixels { width: w, data: vec![0; (w * h * 4) as usize] };
+        assert!(r.build(Rc::new(px), &|| 0.0, 0, f64::INFINITY));
+        (regions, r)
+    }
+
+    #[test]
+    fn quads_cull_across_the_region_border() {
+        let world = World { region: U::CHUNK, ..World::new(0, 1, 0, 0) };
+        let mut mesh = create_mesh();
+        mesh.quads(true);
+        let regions = regions(&mesh, world);
+        let (w, h) = world.atlas();
+        let solid = || Rc::new(Pixels { width: w, data: vec![255; (w * h * 4) as usize] });
+        // the east region loads first, so the west one finds it when culling
+        let (west, east) = (regions.ensure(0, 0), regions.ensure(1, 0));
+        assert!(east.build(solid(), &|| 0.0, 1, f64::INFINITY));
+        assert!(west.build(solid(), &|| 0.0, 0, f64::INFINITY));
+        let faces = |r: &Region| {
+            let c = r.chunk_at(0, 0, 0).unwrap();
+            c.with_mesh(|m| (0..m.cnt as usize).map(|q| (m.dir[q] as usize, m.pos[q * 3])).collect::<Vec<_>>())
+        };
+        let (w, e) = (faces(&west), faces(&east));
+        // a solid cube has six faces; the shared wall is gone on the west side only
+        assert_eq!(e.len(), 6);
+        assert_eq!(w.len(), 5);
+        assert!(!w.iter().any(|f| f.0 == 0));
+    }
+
+    #[test]
+    fn failed_undo_keeps_the_cursor() {
+        let (_, r) = tiny();
+        assert!(r.set_voxel(1, 2, 3, [9, 9, 9]));
+        assert!(r.undo().is_some() && r.voxel(1, 2, 3) == 0);
+        // nothing left to write into
+        r.clear();
+        assert!(r.redo().is_none());
+        assert!(r.can_redo() && !r.can_undo());
+    }
+
+    #[test]
+    fn apply_diff_drops_edits_it_does_not_have() {
+        let (_, a) = tiny();
+        assert!(a.set_voxel(4, 4, 4, [1, 1, 1]));
+        let (_, b) = tiny();
+        assert!(b.set_voxel(5, 5, 5, [2, 2, 2]));
+        assert!(a.apply_diff(&b.diff()));
+        assert_eq!((a.voxel(4, 4, 4), a.voxel(5, 5, 5)), (0, 1));
+        assert!(!a.can_undo());
+    }
+
+    #[test]
+    fn manifest_world_replaces_the_layout() {
+        let regions = regions(&create_mesh(), World::default());
+        regions.ensure(30, 76);
+        assert_eq!(regions.len(), 1);
+        let mut m = Manifest::new("v2", Some(World { region: 128, prefetch: 9, ..World::new(0, 3, 0, 3) }));
+        m.add(1, 2, 17, &[1, 2, 3]);
+        regions.set_manifest(m);
+        let w = regions.world();
+        assert_eq!((w.x1, w.region), (3, 128));
+        // the page's search radius is not the dataset's to change
+        assert_eq!(w.prefetch, World::default().prefetch);
+        assert!(regions.is_empty());
+        assert!(regions.listed(1, 2) && !regions.listed(2, 2));
+    }
+
+    #[test]
+    fn voxel_reads_the_atlas_through_world_coordinates() {
+        let world = World { region: 2 * U::CHUNK, origin: [100.0, 0.0, 0.0], ..World::new(0, 0, 0, 0) };
+        let regions = regions(&create_mesh(), world);
+        let r = regions.ensure(0, 0);
+        let (w, h) = world.atlas();
+        let mut data = vec![0; (w * h * 4) as usize];
+        // voxel (3, 5, 6) of chunk (1, 0, 1): column 6 & 3 and row 6 >> 2 of its tile
+        let (tx, ty) = world.tile(1, 0, 1);
+        let (px, py) = (tx + 2 * 16 + 3, ty + 16 + 5);
+        data[((py * w + px) * 4 + 3) as usize] = 255;
+        assert!(r.build(Rc::new(Pixels { width: w, data }), &|| 0.0, 0, f64::INFINITY));
+        let (lx, ly, lz) = (16 + 3, 5, 16 + 6);
+        assert_eq!(r.voxel(lx, ly, lz), 1);
+        assert_eq!(r.voxel(lx + 1, ly, lz), 0);
+        assert_eq!(regions.voxel(100.0 + lx as f32 + 0.5, ly as f32 + 0.5, lz as f32 + 0.5), 1);
+        assert_eq!(regions.voxel(100.0 + lx as f32 + 0.5, ly as f32 + 1.5, lz as f32 + 0.5), 0);
+        // outside the world reads as air
+        assert_eq!(regions.voxel(99.0, 5.5, 0.5), 0);
+    }
+
+    #[test]
+    fn select_keeps_the_nearest_and_evict_drops_the_farthest() {
+        let world = World { region: U::CHUNK, prefetch: 2, near: 0, ..World::new(0, 9, 0, 9) };
+        let regions = regions(&create_mesh(), world);
+        regions.set_slots(3);
+        let (x, y, z) = world.off_of(5, 5);
+        let (keep, near) = regions.select([x + 8.0, y, z + 8.0], &ALL, false);
+        assert!(near.is_empty());
+        // a 4x4 search around (5, 5); the camera's own region always comes first
+        assert_eq!(regions.len(), 16);
+        assert_eq!(keep.len(), 3);
+        assert_eq!((keep[0].i, keep[0].j), (5, 5));
+        assert!(keep.iter().all(|r| (r.i - 5).abs() + (r.j - 5).abs() <= 1));
+        regions.set_budget(Budget { regions: 8, ..Budget::default() });
+        let active: HashSet<i32> = keep.iter().map(|r| r.id).collect();
+        let gone = regions.evict(&active, 5, 5);
+        assert_eq!((gone.len(), regions.len()), (8, 8));
+        assert!(active.iter().all(|id| regions.region(*id).is_some()));
+        // (3, 3) is the farthest corner and leaves first
+        assert_eq!((gone[0].i, gone[0].j), (3, 3));
+        assert_eq!(regions.stats().evicted, 8);
+    }
+
+    #[test]
+    fn adopted_quads_are_culled_in_the_worker_and_at_the_border() {
+        let world = World { region: 2 * U::CHUNK, ..World::new(0, 1, 0, 0) };
+        let mut mesh = create_mesh();
+        mesh.quads(true);
+        let regions = regions(&mesh, world);
+        let (w, h) = world.atlas();
+        let solid = || Pixels { width: w, data: vec![255; (w * h * 4) as usize] };
+        let (west, east) = (regions.ensure(0, 0), regions.ensure(1, 0));
+        assert!(east.build(Rc::new(solid()), &|| 0.0, 1, f64::INFINITY));
+        let baked = crate::bake::bake(&solid(), &world, Mesher::Quad);
+        let dirs = |m: &Meshed| {
+            let mut d: Vec<u32> = m.dir.iter().map(|d| *d as u32).collect();
+            d.sort();
+            d.dedup();
+            d
+        };
+        // inside the region nothing faces another chunk, but the worker can't see the east region
+        let parts = baked.split();
+        assert_eq!(dirs(&parts[0].3), vec![1, 3, 5]);
+        assert_eq!(dirs(&parts[1].3), vec![0, 3, 5]);
+        west.adopt(&baked);
+        let faces = |ci: i32| west.chunk_at(ci, 0, 0).unwrap().with_mesh(dirs);
+        assert_eq!(faces(0), vec![1, 3, 5]);
+        assert_eq!(faces(1), vec![3, 5]);
+    }
 }
@@ -1,7 +1,10 @@
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use js_sys::{ Set, Reflect, Function };
+use std::rc::Rc;
+use crate::host::{ Atlas, Clock, Pixels };
+use crate::region::{ Region, Regions };
 use crate::utils as U;
+use crate::web as W;
 use web_sys::{
     CanvasRenderingContext2d,
     WebGl2RenderingContext,
@@ -12,36 +15,77 @@ use web_sys::{
 
 #[wasm_bindgen]
 struct Slot {
-    ctx: CanvasRenderingContext2d,
+    // the unit backend's own canvas; array slots keep their region's pixels in src instead
+    ctx: Option<CanvasRenderingContext2d>,
+    // what the region's chunks are read from, set once its picture is drawn
+    src: Option<Rc<dyn Atlas>>,
     tex: Option<WebGlTexture>,
     atlas: Option<WebGlUniformLocation>,
     offset: Option<WebGlUniformLocation>,
-    region: Option<JsValue>,
+    region: Option<Region>,
     is_ready: bool,
-    index: i32,
 }
+
+// the array backend: every slot is a layer of one TEXTURE_2D_ARRAY sampled through a single
+// `uniform sampler2DArray iAtlas` as texelFetch(iAtlas, ivec3(uv, aid), 0), with offsets in
+// `uniform vec3 iOffset[n]`, so the slot count is bounded by texture memory, not units
+struct Layers {
+    tex: Option<WebGlTexture>,
+    atlas: Option<WebGlUniformLocation>,
+    unit: u32,
+    // layer size, the world's atlas; a world with another layout reallocates every layer
+    size: (u32, u32),
+    // one canvas every picture is decoded through on its way to a layer
+    scratch: Option<CanvasRenderingContext2d>,
+}
+
 #[wasm_bindgen]
 pub struct Slots {
     owner: Vec<Slot>,
-    pending: Vec<JsValue>,
+    pending: Vec<Region>,
     cursor: usize,
-    keep: Set,
+    // None binds slot i to TEXTURE0 + i as `iAtlas{i}` with `iOffset{i}`, capped by texture units
+    layers: Option<Layers>,
 }
 
 #[wasm_bindgen(js_name = createSlots)]
 pub fn create_slots(size: i32) -> Slots {
-    let owner = (0..size)
-        .map(|i| Slot {
-            ctx: U::create_context(),
+    let owner = slots(size, true);
+    Slots { owner, pending: Vec::new(), cursor: 0, layers: None }
+}
+
+// size layers of RGBA as large as the world's atlas, sampled from texture unit `unit`
+#[wasm_bindgen(js_name = createArraySlots)]
+pub fn create_array_slots(size: i32, unit: u32) -> Slots {
+    let owner = slots(size, false);
+    let layers = Layers { tex: None, atlas: None, unit, size: (0, 0), scratch: None };
+    Slots { owner, pending: Vec::new(), cursor: 0, layers: Some(layers) }
+}
+
+fn slots(size: i32, canvas: bool) -> Vec<Slot> {
+    (0..size.max(0))
+        .map(|_| Slot {
+            ctx: canvas.then(W::create_context),
+            src: None,
             tex: None,
             atlas: None,
             offset: None,
             region: None,
             is_ready: false,
-            index: i,
         })
-        .collect();
-    Slots { owner, pending: Vec::new(), cursor: 0, keep: Set::new(&JsValue::undefined()) }
+        .collect()
+}
+
+fn filters(c: &WebGl2RenderingContext, target: u32) {
+    let params = [
+        (WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::LINEAR),
+        (WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::LINEAR),
+        (WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE),
+        (WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE),
+    ];
+    for (k, v) in params {
+        c.tex_parameteri(target, k, v as i32);
+    }
 }
 
 #[wasm_bindgen]
@@ -50,187 +94,153 @@ impl Slots {
         &mut self,
         c: &WebGl2RenderingContext,
         pg: &WebGlProgram,
-        r: JsValue,
+        r: Region,
         budget: i32
     ) -> bool {
-        let mut index = Reflect::get(&r, &JsValue::from_str("slot"))
-            .unwrap_or(JsValue::from_f64(-1.0))
-            .as_f64()
-            .unwrap_or(-1.0) as i32;
-        let region_is_same = if index >= 0 {
-            let this_id = Reflect::get(&r, &JsValue::from_str("id"))
-                .unwrap()
-                .as_f64()
-                .unwrap_or(0.0) as i32;
-            self.owner[index as usize].region
-                .as_ref()
-                .map(
-                    |x|
-                        Reflect::get(x, &JsValue::from_str("id"))
-                            .unwrap()
-                            .as_f64()
-                            .unwrap_or(0.0) as i32
-                ) == Some(this_id)
-        } else {
-            false
-        };
+        let mut index = r.slot_index();
         if index < 0 {
             if let Some(i) = self.owner.iter().position(|s| s.region.is_none()) {
                 index = i as i32;
-                {
-                    let slot = &mut self.owner[i as usize];
-                    slot.region = Some(r.clone());
-                    slot.is_ready = false;
-                }
-                let _ = Reflect::set(
-                    &r,
-                    &JsValue::from_str("slot"),
-                    &JsValue::from_f64(index as f64)
-                );
+                let slot = &mut self.owner[i];
+                slot.region = Some(r.clone());
+                slot.src = None;
+                slot.is_ready = false;
+                r.assign_slot(index);
             } else {
                 return false;
             }
-        } else {
-            if !region_is_same {
-                return false;
-            }
-        }
-        let need_ready = {
-            let s = &self.owner[index as usize];
-            !s.is_ready
-        };
-        if need_ready {
-            if !self.ready(c, pg, index) {
-                return false;
-            }
+        } else if self.owner[index as usize].region.as_ref().map(|x| x.id) != Some(r.id) {
+            return false;
         }
-        let chunk_fn: Function = Reflect::get(&r, &JsValue::from_str("chunk"))
-            .unwrap()
-            .unchecked_into();
-        let ctx2 = { self.owner[index as usize].ctx.clone() };
-        let ok = chunk_fn
-            .call3(&r, &ctx2, &JsValue::from_f64(index as f64), &JsValue::from_f64(budget as f64))
-            .unwrap()
-            .as_bool()
-            .unwrap_or(false);
-        if !ok {
+        if !self.owner[index as usize].is_ready && !self.ready(c, pg, index) {
             return false;
         }
-        true
+        let Some(src) = self.owner[index as usize].src.clone() else {
+            return false;
+        };
+        r.build(src, &W::Now, index, budget as f64)
     }
     fn ready(&mut self, c: &WebGl2RenderingContext, pg: &WebGlProgram, index: i32) -> bool {
+        let depth = self.owner.len() as i32;
+        if let (Some(l), Some(r)) = (self.layers.as_mut(), self.owner[index as usize].region.as_ref()) {
+            // layers of another size hold nothing this world can use
+            if l.tex.is_some() && l.size != r.world().atlas() {
+                c.delete_texture(l.tex.as_ref());
+                l.tex = None;
+                for s in self.owner.iter_mut() {
+                    s.is_ready = false;
+                }
+            }
+        }
         let slot = &mut self.owner[index as usize];
         let Some(r) = slot.region.as_ref() else {
             return true;
         };
-        let peek: Function = Reflect::get(r, &JsValue::from_str("peek")).unwrap().unchecked_into();
-        let prefetch: Function = Reflect::get(r, &JsValue::from_str("prefetch"))
-            .unwrap()
-            .unchecked_into();
-        let img = peek.call0(r).unwrap();
-        if img.is_undefined() {
-            let _ = prefetch.call1(r, &JsValue::from_f64(2.0));
+        let Some(img) = r.element() else {
+            let _ = r.prefetch(2);
             return false;
+        };
+        let size = r.world().atlas();
+        let off = [r.x, r.y, r.z];
+        match self.layers.as_mut() {
+            Some(l) => {
+                if slot.offset.is_none() {
+                    slot.offset = c.get_uniform_location(pg, &format!("iOffset[{}]", index));
+                }
+                let target = WebGl2RenderingContext::TEXTURE_2D_ARRAY;
+                c.active_texture(WebGl2RenderingContext::TEXTURE0 + l.unit);
+                if l.tex.is_none() {
+                    l.tex = c.create_texture();
+                    l.size = size;
+                    c.bind_texture(target, l.tex.as_ref());
+                    filters(c, target);
+                    c.tex_storage_3d(target, 1, WebGl2RenderingContext::RGBA8, size.0 as i32, size.1 as i32, depth);
+                    l.atlas = c.get_uniform_location(pg, "iAtlas");
+                }
+                c.bind_texture(target, l.tex.as_ref());
+                // decoded once here; the layer and the region's chunk reads both take these pixels
+                let ctx = l.scratch.get_or_insert_with(W::create_context);
+                ctx.clear_rect(0.0, 0.0, size.0 as f64, size.1 as f64);
+                img.draw(ctx, size.0 as f64, size.1 as f64);
+                let Ok(data) = ctx.get_image_data(0.0, 0.0, size.0 as f64, size.1 as f64) else {
+                    return false;
+                };
+                let _ = c.tex_sub_image_3d_with_image_data(
+                    target,
+                    0,
+                    0,
+                    0,
+                    index,
+                    size.0 as i32,
+                    size.1 as i32,
+                    1,
+                    WebGl2RenderingContext::RGBA,
+                    WebGl2RenderingContext::UNSIGNED_BYTE,
+                    &data
+                );
+                slot.src = Some(Rc::new(Pixels { width: size.0, data: data.data().to_vec() }));
+                if let Some(a) = l.atlas.as_ref() {
+                    c.uniform1i(Some(a), l.unit as i32);
+                }
+            }
+            None => {
+                if slot.atlas.is_none() {
+                    slot.atlas = c.get_uniform_location(pg, &format!("iAtlas{}", index));
+                }
+                if slot.offset.is_none() {
+                    slot.offset = c.get_uniform_location(pg, &format!("iOffset{}", index));
+                }
+                if slot.tex.is_none() {
+                    slot.tex = c.create_texture();
+                }
+                c.active_texture(WebGl2RenderingContext::TEXTURE0 + (index as u32));
+                c.bind_texture(WebGl2RenderingContext::TEXTURE_2D, slot.tex.as_ref());
+                filters(c, WebGl2RenderingContext::TEXTURE_2D);
+                let ctx = slot.ctx.get_or_insert_with(W::create_context).clone();
+                ctx.clear_rect(0.0, 0.0, U::ATLAS as f64, U::ATLAS as f64);
+                img.draw(&ctx, size.0 as f64, size.1 as f64);
+                img.upload(c);
+                slot.src = Some(Rc::new(ctx));
+                if let Some(a) = slot.atlas.as_ref() {
+                    c.uniform1i(Some(a), index);
+                }
+            }
         }
-        let img: web_sys::HtmlImageElement = img.unchecked_into();
-        if slot.atlas.is_none() {
-            slot.atlas = c.get_uniform_location(pg, &format!("iAtlas{}", index));
-        }
-        if slot.offset.is_none() {
-            slot.offset = c.get_uniform_location(pg, &format!("iOffset{}", index));
-        }
-        if slot.tex.is_none() {
-            slot.tex = c.create_texture();
-        }
-        c.active_texture(WebGl2RenderingContext::TEXTURE0 + (index as u32));
-        c.bind_texture(WebGl2RenderingContext::TEXTURE_2D, slot.tex.as_ref());
-        c.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_MIN_FILTER,
-            WebGl2RenderingContext::LINEAR as i32
-        );
-        c.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_MAG_FILTER,
-            WebGl2RenderingContext::LINEAR as i32
-        );
-        c.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_WRAP_S,
-            WebGl2RenderingContext::CLAMP_TO_EDGE as i32
-        );
-        c.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_WRAP_T,
-            WebGl2RenderingContext::CLAMP_TO_EDGE as i32
-        );
-        slot.ctx.clear_rect(0.0, 0.0, 4096.0, 4096.0);
-        let _ = slot.ctx.draw_image_with_html_image_element_and_dw_and_dh(
-            &img,
-            0.0,
-            0.0,
-            4096.0,
-            4096.0
-        );
-        let _ = c.tex_image_2d_with_u32_and_u32_and_html_image_element(
-            WebGl2RenderingContext::TEXTURE_2D,
-            0,
-            WebGl2RenderingContext::RGBA as i32,
-            WebGl2RenderingContext::RGBA,
-            WebGl2RenderingContext::UNSIGNED_BYTE,
-            &img
-        );
-        if let Some(a) = slot.atlas.as_ref() {
-            c.uniform1i(Some(a), index as i32);
-        }
+        let slot = &mut self.owner[index as usize];
         if let Some(of) = slot.offset.as_ref() {
-            let off = [
-                Reflect::get(r, &JsValue::from_str("x")).unwrap().as_f64().unwrap_or(0.0) as f32,
-                Reflect::get(r, &JsValue::from_str("y")).unwrap().as_f64().unwrap_or(0.0) as f32,
-                Reflect::get(r, &JsValue::from_str("z")).unwrap().as_f64().unwrap_or(0.0) as f32,
-            ];
             c.uniform3fv_with_f32_array(Some(of), &off);
         }
         slot.is_ready = true;
         true
     }
-    pub fn begin(&mut self, set: &Set) {
-        let mut i = 0usize;
-        while i < self.owner.len() {
-            let (keep_this, region_js) = if let Some(r) = self.owner[i].region.as_ref() {
-                (set.has(r), Some(r.clone()))
-            } else {
-                (false, None)
+    // also tells regions how many it may keep visible, which is as many as there are slots
+    pub fn begin(&mut self, regions: &Regions) {
+        regions.set_slots(self.owner.len());
+        let keep = regions.keep();
+        for slot in self.owner.iter_mut() {
+            let Some(r) = slot.region.as_ref() else {
+                continue;
             };
-            if !keep_this {
-                if let Some(r) = region_js {
-                    let _ = Reflect::set(&r, &JsValue::from_str("slot"), &JsValue::from_f64(-1.0));
-                }
-                self.owner[i].region = None;
-                self.owner[i].is_ready = false;
+            if keep.iter().any(|k| k.id == r.id) {
+                continue;
             }
-            i += 1;
+            r.assign_slot(-1);
+            slot.region = None;
+            slot.src = None;
+            slot.is_ready = false;
         }
-        self.keep = set.clone();
         self.cursor = 0;
-        self.pending = vec![];
-        let it = js_sys::try_iter(&set).unwrap().unwrap();
-        for v in it {
-            let r = v.unwrap();
-            let cursor_fn: Function = Reflect::get(&r, &JsValue::from_str("cursor"))
-                .unwrap()
-                .unchecked_into();
-            let _ = cursor_fn.call0(&r);
-            self.pending.push(r);
+        for r in keep.iter() {
+            r.rewind();
         }
+        self.pending = keep;
     }
     pub fn step(&mut self, c: &JsValue, pg: &JsValue, budget: i32) -> bool {
         let c: WebGl2RenderingContext = c.clone().unchecked_into();
         let pg: WebGlProgram = pg.clone().unchecked_into();
-        let start = web_sys::window().unwrap().performance().unwrap().now();
+        let start = W::Now.now();
         while self.cursor < self.pending.len() {
-            let dt = ((budget as f64) -
-                (web_sys::window().unwrap().performance().unwrap().now() - start)) as i32;
+            let dt = ((budget as f64) - (W::Now.now() - start)) as i32;
             if dt <= 0 {
                 return false;
             }
@@ -0,0 +1,105 @@
+use wasm_bindgen::prelude::*;
+use js_sys::{ Function, Promise };
+use web_sys::AbortSignal;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::utils as U;
+use crate::web as W;
+use crate::host::Pixels;
+
+// where region atlases come from
+pub enum Source {
+    // {z}, {i}, {j} and {ext} are substituted, e.g. "https://host/atlas/v1/{z}_{i}_{j}.{ext}"
+    Url { template: String, ext: String },
+    // (i, j, z, signal) => Promise of an HTMLImageElement, or of a URL to load one from;
+    // signal is an AbortSignal that fires when the region no longer wants the atlas
+    Loader(Function),
+    // decoded atlases handed over up front, for tests and offline installs
+    Memory(HashMap<(i32, i32), Rc<Pixels>>),
+}
+
+// how hard a region tries before giving up on its atlas; times in milliseconds
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Retry {
+    pub tries: u32,
+    // wait before the second try, doubled for each one after
+    pub backoff: f64,
+    // per try; 0 waits forever
+    pub timeout: f64,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry { tries: 3, backoff: 500.0, timeout: 15000.0 }
+    }
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Source::Url { template: format!("{}/{{i}}_{{j}}.{{ext}}", U::ATLAS_URL), ext: "png".to_string() }
+    }
+}
+
+impl Source {
+    pub fn url(&self, z: i32, i: i32, j: i32) -> Option<String> {
+        let Source::Url { template, ext } = self else {
+            return None;
+        };
+        let out = template
+            .replace("{z}", &z.to_string())
+            .replace("{i}", &i.to_string())
+            .replace("{j}", &j.to_string())
+            .replace("{ext}", ext);
+        Some(out)
+    }
+    pub fn local(&self, i: i32, j: i32) -> Option<Rc<Pixels>> {
+        let Source::Memory(m) = self else {
+            return None;
+        };
+        m.get(&(i, j)).cloned()
+    }
+    pub fn insert(&mut self, i: i32, j: i32, px: Pixels) {
+        if !matches!(self, Source::Memory(_)) {
+            *self = Source::Memory(HashMap::new());
+        }
+        if let Source::Memory(m) = self {
+            m.insert((i, j), Rc::new(px));
+        }
+    }
+    // promise of the atlas image; None when the source has nothing to download
+    pub fn fetch(&self, z: i32, i: i32, j: i32, signal: &AbortSignal) -> Option<Promise> {
+        if let Some(url) = self.url(z, i, j) {
+            return Some(W::create_image(&url, Some(signal)));
+        }
+        let Source::Loader(f) = self else {
+            return None;
+        };
+        let args = [i, j, z].map(|v| JsValue::from_f64(v as f64));
+        let p: Promise = match f.call4(&JsValue::NULL, &args[0], &args[1], &args[2], signal) {
+            Ok(v) => Promise::resolve(&v),
+            Err(e) => Promise::reject(&e),
+        };
+        // resolving with the image promise adopts it, so callers always get an element
+        let out = Promise::new(&mut |resolve: Function, reject: Function| {
+            let signal = signal.clone();
+            let ok = Closure::wrap(
+                Box::new(move |v: JsValue| {
+                    let v = match v.as_string() {
+                        Some(url) => W::create_image(&url, Some(&signal)).into(),
+                        None => v,
+                    };
+                    let _ = resolve.call1(&JsValue::NULL, &v);
+                }) as Box<dyn FnMut(JsValue)>
+            );
+            let err = Closure::wrap(
+                Box::new(move |e: JsValue| {
+                    let _ = reject.call1(&JsValue::NULL, &e);
+                }) as Box<dyn FnMut(JsValue)>
+            );
+            let _ = p.then2(&ok, &err);
+            ok.forget();
+            err.forget();
+        });
+        Some(out)
+    }
+}
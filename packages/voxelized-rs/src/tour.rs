@@ -0,0 +1,104 @@
+use serde::{ Deserialize, Serialize };
+
+// one camera pose at t seconds from the start of the tour
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Key {
+    pub t: f32,
+    pub pos: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Key {
+    fn channels(&self) -> [f32; 5] {
+        [self.pos[0], self.pos[1], self.pos[2], self.yaw, self.pitch]
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Tour {
+    pub keys: Vec<Key>,
+}
+
+impl Tour {
+    // keys must move forward in time; yaw is unwrapped so playback turns the short way round
+    pub fn push(&mut self, mut k: Key) -> bool {
+        if let Some(last) = self.keys.last() {
+            if k.t <= last.t {
+                return false;
+            }
+            let tau = std::f32::consts::TAU;
+            k.yaw = last.yaw + (k.yaw - last.yaw + std::f32::consts::PI).rem_euclid(tau) - std::f32::consts::PI;
+        }
+        self.keys.push(k);
+        true
+    }
+    pub fn duration(&self) -> f32 {
+        match (self.keys.first(), self.keys.last()) {
+            (Some(a), Some(b)) => b.t - a.t,
+            _ => 0.0,
+        }
+    }
+    // Catmull-Rom through the keys, with tangents scaled to their uneven spacing in time
+    pub fn sample(&self, t: f32) -> Option<Key> {
+        let k = &self.keys;
+        let first = k.first()?;
+        let t = t + first.t;
+        if k.len() == 1 || t <= first.t {
+            return Some(*first);
+        }
+        let i = k.partition_point(|x| x.t <= t);
+        if i >= k.len() {
+            return k.last().copied();
+        }
+        let (a, b) = (i - 1, i);
+        let p0 = k[a.saturating_sub(1)];
+        let (p1, p2) = (k[a], k[b]);
+        let p3 = k[(b + 1).min(k.len() - 1)];
+        let dt = p2.t - p1.t;
+        let s = (t - p1.t) / dt;
+        let (s2, s3) = (s * s, s * s * s);
+        let h00 = 2.0 * s3 - 3.0 * s2 + 1.0;
+        let h10 = s3 - 2.0 * s2 + s;
+        let h01 = -2.0 * s3 + 3.0 * s2;
+        let h11 = s3 - s2;
+        let (c0, c1, c2, c3) = (p0.channels(), p1.channels(), p2.channels(), p3.channels());
+        let mut out = [0.0; 5];
+        for c in 0..5 {
+            let m1 = (c2[c] - c0[c]) / (p2.t - p0.t);
+            let m2 = (c3[c] - c1[c]) / (p3.t - p1.t);
+            out[c] = h00 * c1[c] + h10 * dt * m1 + h01 * c2[c] + h11 * dt * m2;
+        }
+        Some(Key { t: t - first.t, pos: [out[0], out[1], out[2]], yaw: out[3], pitch: out[4] })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(t: f32, x: f32, yaw: f32) -> Key {
+        Key { t, pos: [x, 2.0, 0.0], yaw, pitch: 0.0 }
+    }
+
+    #[test]
+    fn sample_passes_through_the_keys_and_turns_the_short_way() {
+        let mut tour = Tour::default();
+        assert!(tour.push(key(1.0, 0.0, 3.0)));
+        assert!(!tour.push(key(1.0, 5.0, 3.0)));
+        // -3 is a small turn past pi, not nearly a full one back
+        assert!(tour.push(key(2.0, 10.0, -3.0)));
+        assert!(tour.push(key(4.0, 20.0, -3.0)));
+        let tau = std::f32::consts::TAU;
+        assert!((tour.keys[1].yaw - (tau - 3.0)).abs() < 1e-5);
+        assert_eq!(tour.duration(), 3.0);
+        // times are from the first key
+        assert_eq!(tour.sample(-1.0), Some(tour.keys[0]));
+        let k = tour.sample(1.0).unwrap();
+        assert!((k.pos[0] - 10.0).abs() < 1e-5 && (k.yaw - tour.keys[1].yaw).abs() < 1e-5);
+        let mid = tour.sample(0.5).unwrap();
+        assert!(mid.pos[0] > 0.0 && mid.pos[0] < 10.0);
+        assert!(mid.yaw > 3.0 && mid.yaw < tau - 3.0);
+        assert_eq!(tour.sample(10.0), tour.keys.last().copied());
+    }
+}
@@ -1,31 +1,14 @@
-use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-use js_sys::{ Function, Promise };
-
-pub const SCOPE_X0: i32 = 28;
-pub const SCOPE_X1: i32 = 123;
-pub const SCOPE_Y0: i32 = 75;
-pub const SCOPE_Y1: i32 = 79;
-pub const ROW: i32 = SCOPE_X1 - SCOPE_X0 + 1;
+// defaults only; a running world reads these from World
 pub const SLOT: i32 = 16;
 pub const CHUNK: i32 = 16;
-pub const CACHE: i32 = 32;
 pub const REGION: i32 = 256;
 pub const PREFETCH: i32 = 16;
+// atlas side in pixels of the largest region, CHUNK chunks a side
+pub const ATLAS: i32 = 4096;
+// pixels per chunk tile: its CHUNK slices laid 4 across
+pub const TILE: i32 = CHUNK * 4;
 pub const ATLAS_URL: &str = "https://pub-a3916cfad25545dc917e91549e7296bc.r2.dev/v1";
 
-pub fn off_of(i: i32, j: i32) -> (f32, f32, f32) {
-    (((i - SCOPE_X0) * REGION) as f32, 0.0, ((SCOPE_Y1 - j) * REGION) as f32)
-}
-pub fn pos_of(pos: &[f32; 3]) -> (i32, i32) {
-    (
-        SCOPE_X0 + ((pos[0] / (REGION as f32)).floor() as i32),
-        SCOPE_Y1 - ((pos[2] / (REGION as f32)).floor() as i32),
-    )
-}
-pub fn region_id(i: i32, j: i32) -> i32 {
-    i + ROW * j
-}
 pub fn chunk_id(i: i32, j: i32, k: i32) -> i32 {
     i + j * CHUNK + k * CHUNK * CHUNK
 }
@@ -39,6 +22,29 @@ pub fn perspective(out: &mut [f32; 16], fovy: f32, aspect: f32, near: f32, far:
     out[11] = -1.0;
     out[14] = (2.0 * far * near) / (near - far);
 }
+// reversed-Z with the far plane at infinity: depth is 1 at near and falls towards 0,
+// so draw with depthFunc(GEQUAL) and clearDepth(0)
+pub fn perspective_reversed(out: &mut [f32; 16], fovy: f32, aspect: f32, near: f32) {
+    let f = 1.0 / (0.5 * fovy).tan();
+    out.fill(0.0);
+    out[0] = f / aspect;
+    out[5] = f;
+    out[11] = -1.0;
+    out[14] = near;
+}
+pub fn ortho(out: &mut [f32; 16], left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) {
+    let lr = 1.0 / (left - right);
+    let bt = 1.0 / (bottom - top);
+    let nf = 1.0 / (near - far);
+    out.fill(0.0);
+    out[0] = -2.0 * lr;
+    out[5] = -2.0 * bt;
+    out[10] = 2.0 * nf;
+    out[12] = (left + right) * lr;
+    out[13] = (top + bottom) * bt;
+    out[14] = (far + near) * nf;
+    out[15] = 1.0;
+}
 pub fn look_at(out: &mut [f32; 16], eye: [f32; 3], center: [f32; 3], up: [f32; 3]) {
     let (eyex, eyey, eyez) = (eye[0], eye[1], eye[2]);
     let (upx, upy, upz) = (up[0], up[1], up[2]);
@@ -91,12 +97,53 @@ pub fn mul(out: &mut [f32; 16], a: &[f32; 16], b: &[f32; 16]) {
     out[15] = a03 * b30 + a13 * b31 + a23 * b32 + a33 * b33;
 }
 
+pub fn invert(out: &mut [f32; 16], a: &[f32; 16]) -> bool {
+    let a00 = a[0];  let a01 = a[1];  let a02 = a[2];  let a03 = a[3];
+    let a10 = a[4];  let a11 = a[5];  let a12 = a[6];  let a13 = a[7];
+    let a20 = a[8];  let a21 = a[9];  let a22 = a[10]; let a23 = a[11];
+    let a30 = a[12]; let a31 = a[13]; let a32 = a[14]; let a33 = a[15];
+    let b00 = a00 * a11 - a01 * a10;
+    let b01 = a00 * a12 - a02 * a10;
+    let b02 = a00 * a13 - a03 * a10;
+    let b03 = a01 * a12 - a02 * a11;
+    let b04 = a01 * a13 - a03 * a11;
+    let b05 = a02 * a13 - a03 * a12;
+    let b06 = a20 * a31 - a21 * a30;
+    let b07 = a20 * a32 - a22 * a30;
+    let b08 = a20 * a33 - a23 * a30;
+    let b09 = a21 * a32 - a22 * a31;
+    let b10 = a21 * a33 - a23 * a31;
+    let b11 = a22 * a33 - a23 * a32;
+    let det = b00 * b11 - b01 * b10 + b02 * b09 + b03 * b08 - b04 * b07 + b05 * b06;
+    if det == 0.0 {
+        return false;
+    }
+    let det = 1.0 / det;
+    out[0]  = (a11 * b11 - a12 * b10 + a13 * b09) * det;
+    out[1]  = (a02 * b10 - a01 * b11 - a03 * b09) * det;
+    out[2]  = (a31 * b05 - a32 * b04 + a33 * b03) * det;
+    out[3]  = (a22 * b04 - a21 * b05 - a23 * b03) * det;
+    out[4]  = (a12 * b08 - a10 * b11 - a13 * b07) * det;
+    out[5]  = (a00 * b11 - a02 * b08 + a03 * b07) * det;
+    out[6]  = (a32 * b02 - a30 * b05 - a33 * b01) * det;
+    out[7]  = (a20 * b05 - a22 * b02 + a23 * b01) * det;
+    out[8]  = (a10 * b10 - a11 * b08 + a13 * b06) * det;
+    out[9]  = (a01 * b08 - a00 * b10 - a03 * b06) * det;
+    out[10] = (a30 * b04 - a31 * b02 + a33 * b00) * det;
+    out[11] = (a21 * b02 - a20 * b04 - a23 * b00) * det;
+    out[12] = (a11 * b07 - a10 * b09 - a12 * b06) * det;
+    out[13] = (a00 * b09 - a01 * b07 + a02 * b06) * det;
+    out[14] = (a31 * b01 - a30 * b03 - a32 * b00) * det;
+    out[15] = (a20 * b03 - a21 * b01 + a22 * b00) * det;
+    true
+}
+
 pub fn rotate_y(out: &mut [f32; 16], a: &[f32; 16], rad: f32) {
     let s = rad.sin();
     let c = rad.cos();
     let a00 = a[0];  let a01 = a[1];  let a02 = a[2];  let a03 = a[3];
     let a20 = a[8];  let a21 = a[9];  let a22 = a[10]; let a23 = a[11];
-    if a as *const _ != out as *const _ {
+    if !std::ptr::eq(a, out) {
         out[4] = a[4]; out[5] = a[5]; out[6] = a[6]; out[7] = a[7];
         out[12] = a[12]; out[13] = a[13]; out[14] = a[14]; out[15] = a[15];
     }
@@ -115,7 +162,7 @@ pub fn rotate_x(out: &mut [f32; 16], a: &[f32; 16], rad: f32) {
     let c = rad.cos();
     let a10 = a[4];  let a11 = a[5];  let a12 = a[6];  let a13 = a[7];
     let a20 = a[8];  let a21 = a[9];  let a22 = a[10]; let a23 = a[11];
-    if a as *const _ != out as *const _ {
+    if !std::ptr::eq(a, out) {
         out[0] = a[0]; out[1] = a[1]; out[2] = a[2]; out[3] = a[3];
         out[12] = a[12]; out[13] = a[13]; out[14] = a[14]; out[15] = a[15];
     }
@@ -164,88 +211,3 @@ pub fn vis_sphere(m: &[f32; 16], cx: f32, cy: f32, cz: f32, r: f32) -> bool {
     }
     true
 }
-
-#[wasm_bindgen]
-pub fn culling(mvp: &js_sys::Float32Array, rx: f32, ry: f32, rz: f32) -> bool {
-    let mut a = [0.0; 16];
-    mvp.copy_to(&mut a);
-    let (x, y, z) = (rx + 128.0, ry + 128.0, rz + 128.0);
-    vis_sphere(&a, x, y, z, (256.0f32 * 256.0 * 3.0).sqrt() * 0.5)
-}
-
-pub fn range(n: u32) -> js_sys::Array {
-    let a = js_sys::Array::new();
-    for i in 0..n {
-        a.push(&JsValue::from_f64(i as f64));
-    }
-    a
-}
-
-pub fn scoped(i: i32, j: i32) -> bool {
-    SCOPE_X0 <= i && i <= SCOPE_X1 && SCOPE_Y0 <= j && j <= SCOPE_Y1
-}
-
-pub fn create_context() -> web_sys::CanvasRenderingContext2d {
-    let doc = web_sys::window().unwrap().document().unwrap();
-    let canvas: web_sys::HtmlCanvasElement = doc
-        .create_element("canvas")
-        .unwrap()
-        .dyn_into()
-        .unwrap();
-    let options = js_sys::Object::new();
-    js_sys::Reflect
-        ::set(&options, &JsValue::from_str("willReadFrequently"), &JsValue::from_bool(true))
-        .unwrap();
-    canvas.set_width(4096);
-    canvas.set_height(4096);
-    canvas
-        .get_context_with_context_options("2d", &options)
-        .unwrap()
-        .unwrap()
-        .dyn_into::<web_sys::CanvasRenderingContext2d>()
-        .unwrap()
-}
-
-pub fn create_image(src: &str) -> js_sys::Promise {
-    let img = web_sys::HtmlImageElement::new().unwrap();
-    let (p, resolve) = promise_pair();
-    let img2 = img.clone();
-    let onload = Closure::wrap(
-        Box::new(move || {
-            let _ = resolve.call1(&JsValue::NULL, &img2.clone().into());
-        }) as Box<dyn FnMut()>
-    );
-    img.set_onload(Some(onload.as_ref().unchecked_ref()));
-    onload.forget();
-    let _ = img.set_cross_origin(Some("anonymous"));
-    img.set_src(src);
-    p
-}
-
-pub fn scope_js() -> js_sys::Object {
-    let o = js_sys::Object::new();
-    let _ = js_sys::Reflect::set(&o, &JsValue::from_str("x0"), &JsValue::from_f64(SCOPE_X0 as f64));
-    let _ = js_sys::Reflect::set(&o, &JsValue::from_str("x1"), &JsValue::from_f64(SCOPE_X1 as f64));
-    let _ = js_sys::Reflect::set(&o, &JsValue::from_str("y0"), &JsValue::from_f64(SCOPE_Y0 as f64));
-    let _ = js_sys::Reflect::set(&o, &JsValue::from_str("y1"), &JsValue::from_f64(SCOPE_Y1 as f64));
-    o
-}
-
-pub fn get_f32(o: &JsValue, k: &str, def: f32) -> f32 {
-    js_sys::Reflect
-        ::get(o, &JsValue::from_str(k))
-        .ok()
-        .and_then(|v| v.as_f64())
-        .map(|x| x as f32)
-        .unwrap_or(def)
-}
-
-fn promise_pair() -> (Promise, Function) {
-    let mut resolve_fn: Option<Function> = None;
-    let p = Promise::new(
-        &mut (|res: Function, _rej: Function| {
-            resolve_fn = Some(res);
-        })
-    );
-    (p, resolve_fn.unwrap())
-}
@@ -0,0 +1,465 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use js_sys::{ Function, Promise };
+use web_sys::{ CanvasRenderingContext2d, WebGl2RenderingContext, WebGlBuffer, WebGlProgram };
+use crate::host::{ Atlas, Clock, Eye, Scheduler, Upload };
+use crate::cache::Store;
+use crate::navmesh::Surface;
+use crate::world::World;
+use crate::utils as U;
+
+impl Atlas for CanvasRenderingContext2d {
+    fn read(&self, x: u32, y: u32, w: u32, h: u32) -> Vec<u8> {
+        self.get_image_data(x as f64, y as f64, w as f64, h as f64)
+            .map(|d| d.data().to_vec())
+            .unwrap_or_else(|_| vec![0u8; (w * h * 4) as usize])
+    }
+}
+
+pub struct Now;
+
+impl Clock for Now {
+    fn now(&self) -> f64 {
+        web_sys::window().unwrap().performance().unwrap().now()
+    }
+}
+
+#[derive(Default)]
+pub struct Buffers {
+    pos: Option<WebGlBuffer>,
+    scl: Option<WebGlBuffer>,
+    aid: Option<WebGlBuffer>,
+    col: Option<WebGlBuffer>,
+    dir: Option<WebGlBuffer>,
+    len_pos: u32,
+    len_scl: u32,
+    len_aid: u32,
+    len_col: u32,
+    len_dir: u32,
+}
+
+pub struct Gl<'a> {
+    pub c: &'a WebGl2RenderingContext,
+    pub pg: &'a WebGlProgram,
+    pub bufs: &'a mut Buffers,
+}
+
+impl Upload for Gl<'_> {
+    fn attr(&mut self, key: &str, data: &[f32], size: i32) {
+        let c = self.c;
+        let loc = c.get_attrib_location(self.pg, key) as u32;
+        let array = js_sys::Float32Array::from(data);
+        let (buf, len_slot) = match key {
+            "pos" => (&mut self.bufs.pos, &mut self.bufs.len_pos),
+            "scl" => (&mut self.bufs.scl, &mut self.bufs.len_scl),
+            "col" => (&mut self.bufs.col, &mut self.bufs.len_col),
+            "dir" => (&mut self.bufs.dir, &mut self.bufs.len_dir),
+            _ => (&mut self.bufs.aid, &mut self.bufs.len_aid),
+        };
+        if buf.is_none() {
+            *buf = c.create_buffer();
+        }
+        c.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, buf.as_ref());
+        if *len_slot != array.length() {
+            c.buffer_data_with_array_buffer_view(
+                WebGl2RenderingContext::ARRAY_BUFFER,
+                &array,
+                WebGl2RenderingContext::DYNAMIC_DRAW
+            );
+            *len_slot = array.length();
+            c.enable_vertex_attrib_array(loc);
+            c.vertex_attrib_pointer_with_i32(loc, size, WebGl2RenderingContext::FLOAT, false, 0, 0);
+            c.vertex_attrib_divisor(loc, 1);
+        } else {
+            c.buffer_sub_data_with_i32_and_array_buffer_view(
+                WebGl2RenderingContext::ARRAY_BUFFER,
+                0,
+                &array
+            );
+        }
+    }
+    fn patch(&mut self, key: &str, at: usize, data: &[f32]) {
+        let buf = match key {
+            "pos" => &self.bufs.pos,
+            "scl" => &self.bufs.scl,
+            "col" => &self.bufs.col,
+            "dir" => &self.bufs.dir,
+            _ => &self.bufs.aid,
+        };
+        let Some(buf) = buf.as_ref() else {
+            return;
+        };
+        let c = self.c;
+        c.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(buf));
+        c.buffer_sub_data_with_i32_and_array_buffer_view(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            (at * 4) as i32,
+            &js_sys::Float32Array::from(data)
+        );
+    }
+}
+
+// whether the region cube at (rx, ry, rz) can be in view; world sizes the cube as in
+// createRegions, the default one without
+#[wasm_bindgen]
+pub fn culling(mvp: &js_sys::Float32Array, rx: f32, ry: f32, rz: f32, world: JsValue) -> bool {
+    let mut a = [0.0; 16];
+    mvp.copy_to(&mut a);
+    World::from_js(&world).culling(&a, rx, ry, rz)
+}
+
+pub fn range(n: u32) -> js_sys::Array {
+    let a = js_sys::Array::new();
+    for i in 0..n {
+        a.push(&JsValue::from_f64(i as f64));
+    }
+    a
+}
+
+pub fn create_context() -> web_sys::CanvasRenderingContext2d {
+    let doc = web_sys::window().unwrap().document().unwrap();
+    let canvas: web_sys::HtmlCanvasElement = doc
+        .create_element("canvas")
+        .unwrap()
+        .dyn_into()
+        .unwrap();
+    let options = js_sys::Object::new();
+    js_sys::Reflect
+        ::set(&options, &JsValue::from_str("willReadFrequently"), &JsValue::from_bool(true))
+        .unwrap();
+    canvas.set_width(U::ATLAS as u32);
+    canvas.set_height(U::ATLAS as u32);
+    canvas
+        .get_context_with_context_options("2d", &options)
+        .unwrap()
+        .unwrap()
+        .dyn_into::<web_sys::CanvasRenderingContext2d>()
+        .unwrap()
+}
+
+// rejects with an Error when the image fails to load (404, CORS, network);
+// aborting the signal clears src, which makes the browser drop the request
+pub fn create_image(src: &str, signal: Option<&web_sys::AbortSignal>) -> js_sys::Promise {
+    let img = web_sys::HtmlImageElement::new().unwrap();
+    let (p, resolve, reject) = promise_pair();
+    if let Some(signal) = signal {
+        let (img2, reject2) = (img.clone(), reject.clone());
+        on_abort(signal, move || {
+            img2.set_onload(None);
+            img2.set_onerror(None);
+            img2.set_src("");
+            let _ = reject2.call1(&JsValue::NULL, &js_sys::Error::new("aborted").into());
+        });
+    }
+    let img2 = img.clone();
+    let onload = Closure::wrap(
+        Box::new(move || {
+            let _ = resolve.call1(&JsValue::NULL, &img2.clone().into());
+        }) as Box<dyn FnMut()>
+    );
+    let msg = format!("failed to load {}", src);
+    let onerror = Closure::wrap(
+        Box::new(move || {
+            let _ = reject.call1(&JsValue::NULL, &js_sys::Error::new(&msg).into());
+        }) as Box<dyn FnMut()>
+    );
+    img.set_onload(Some(onload.as_ref().unchecked_ref()));
+    img.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+    onload.forget();
+    onerror.forget();
+    img.set_cross_origin(Some("anonymous"));
+    img.set_src(src);
+    p
+}
+
+// adds to whatever else listens on the signal instead of replacing its onabort
+pub fn on_abort(signal: &web_sys::AbortSignal, f: impl FnOnce() + 'static) {
+    let f = Closure::once_into_js(f);
+    let _ = signal.add_event_listener_with_callback("abort", f.unchecked_ref());
+}
+
+// p.then(f) where f may return another promise
+pub fn then(p: &Promise, f: impl FnOnce(JsValue) -> Result<JsValue, JsValue> + 'static) -> Promise {
+    let mut f = Some(f);
+    Promise::new(&mut |resolve: Function, reject: Function| {
+        let f = f.take();
+        let fail = reject.clone();
+        let ok = Closure::once(move |v: JsValue| {
+            let _ = match f.map(|f| f(v)) {
+                Some(Ok(v)) => resolve.call1(&JsValue::NULL, &v),
+                Some(Err(e)) => reject.call1(&JsValue::NULL, &e),
+                None => Ok(JsValue::UNDEFINED),
+            };
+        });
+        let err = Closure::once(move |e: JsValue| {
+            let _ = fail.call1(&JsValue::NULL, &e);
+        });
+        let _ = p.then2(&ok, &err);
+        ok.forget();
+        err.forget();
+    })
+}
+
+// resolves after ms milliseconds
+pub fn delay(ms: f64) -> Promise {
+    let (p, resolve, _) = promise_pair();
+    let _ = web_sys::window()
+        .unwrap()
+        .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms as i32);
+    p
+}
+
+// settles like p, or rejects once ms pass first and aborts ctrl so the work behind p stops
+// too; ms <= 0 waits forever
+pub fn timeout(p: Promise, ms: f64, ctrl: &web_sys::AbortController) -> Promise {
+    if ms <= 0.0 {
+        return p;
+    }
+    let (t, _, reject) = promise_pair();
+    let ctrl = ctrl.clone();
+    let fire = Closure::once_into_js(move || {
+        let _ = reject.call1(&JsValue::NULL, &js_sys::Error::new("timed out").into());
+        ctrl.abort();
+    });
+    let win = web_sys::window().unwrap();
+    let id = win.set_timeout_with_callback_and_timeout_and_arguments_0(fire.unchecked_ref(), ms as i32).unwrap_or(0);
+    // a late abort would blank an image that already loaded
+    let clear = Closure::once(move || {
+        web_sys::window().unwrap().clear_timeout_with_handle(id);
+    });
+    let p = p.finally(&clear);
+    clear.forget();
+    Promise::race(&js_sys::Array::of2(&p, &t))
+}
+
+// message of a rejection reason, whatever was thrown
+pub fn reason(e: &JsValue) -> String {
+    if let Some(err) = e.dyn_ref::<js_sys::Error>() {
+        return err.message().into();
+    }
+    e.as_string().unwrap_or_else(|| "atlas fetch failed".to_string())
+}
+
+pub fn get_f32(o: &JsValue, k: &str, def: f32) -> f32 {
+    js_sys::Reflect
+        ::get(o, &JsValue::from_str(k))
+        .ok()
+        .and_then(|v| v.as_f64())
+        .map(|x| x as f32)
+        .unwrap_or(def)
+}
+
+pub fn promise_pair() -> (Promise, Function, Function) {
+    let mut fns: Option<(Function, Function)> = None;
+    let p = Promise::new(
+        &mut (|res: Function, rej: Function| {
+            fns = Some((res, rej));
+        })
+    );
+    let (resolve, reject) = fns.unwrap();
+    (p, resolve, reject)
+}
+pub fn get_str(o: &JsValue, k: &str) -> Option<String> {
+    js_sys::Reflect::get(o, &JsValue::from_str(k)).ok().and_then(|v| v.as_string())
+}
+
+// a region atlas as it arrives: decoded by the page, or handed over by a worker
+#[derive(Clone)]
+pub enum Picture {
+    Image(web_sys::HtmlImageElement),
+    Bitmap(web_sys::ImageBitmap),
+}
+
+impl Picture {
+    pub fn draw(&self, ctx: &CanvasRenderingContext2d, w: f64, h: f64) {
+        let _ = match self {
+            Picture::Image(img) => ctx.draw_image_with_html_image_element_and_dw_and_dh(img, 0.0, 0.0, w, h),
+            Picture::Bitmap(bmp) => ctx.draw_image_with_image_bitmap_and_dw_and_dh(bmp, 0.0, 0.0, w, h),
+        };
+    }
+    // decoded RGBA size
+    pub fn bytes(&self) -> usize {
+        let (w, h) = match self {
+            Picture::Image(img) => (img.natural_width(), img.natural_height()),
+            Picture::Bitmap(bmp) => (bmp.width(), bmp.height()),
+        };
+        (w as usize) * (h as usize) * 4
+    }
+    // into the TEXTURE_2D bound, at the picture's own size
+    pub fn upload(&self, c: &WebGl2RenderingContext) {
+        let (t, rgba, ty) = (
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+        );
+        let _ = match self {
+            Picture::Image(img) => c.tex_image_2d_with_u32_and_u32_and_html_image_element(t, 0, rgba as i32, rgba, ty, img),
+            Picture::Bitmap(bmp) => c.tex_image_2d_with_u32_and_u32_and_image_bitmap(t, 0, rgba as i32, rgba, ty, bmp),
+        };
+    }
+}
+
+impl From<Picture> for JsValue {
+    fn from(p: Picture) -> JsValue {
+        match p {
+            Picture::Image(img) => img.into(),
+            Picture::Bitmap(bmp) => bmp.into(),
+        }
+    }
+}
+
+// a page-supplied store: { get(key) => Promise<Uint8Array | ArrayBuffer | undefined>,
+// put(key, bytes), delete(key), keys() => Promise<string[]> }, e.g. over IndexedDB or caches
+pub struct JsStore(pub js_sys::Object);
+
+impl JsStore {
+    fn call(&self, name: &str, args: &[&JsValue]) -> Promise {
+        let f = js_sys::Reflect::get(&self.0, &name.into()).ok().and_then(|f| f.dyn_into::<Function>().ok());
+        let Some(f) = f else {
+            return Promise::resolve(&JsValue::UNDEFINED);
+        };
+        let out = match args {
+            [a] => f.call1(&self.0, a),
+            [a, b] => f.call2(&self.0, a, b),
+            _ => f.call0(&self.0),
+        };
+        match out {
+            Ok(v) => Promise::resolve(&v),
+            Err(e) => Promise::reject(&e),
+        }
+    }
+}
+
+impl Store for JsStore {
+    fn get(&self, key: &str, done: Box<dyn FnOnce(Option<Vec<u8>>)>) {
+        let done = std::rc::Rc::new(std::cell::Cell::new(Some(done)));
+        let d = done.clone();
+        let ok = Closure::once(move |v: JsValue| {
+            let bytes = (!v.is_undefined() && !v.is_null()).then(|| js_sys::Uint8Array::new(&v).to_vec());
+            if let Some(f) = d.take() {
+                f(bytes);
+            }
+        });
+        // a store that fails reads as a miss
+        let err = Closure::once(move |_: JsValue| {
+            if let Some(f) = done.take() {
+                f(None);
+            }
+        });
+        let _ = self.call("get", &[&key.into()]).then2(&ok, &err);
+        ok.forget();
+        err.forget();
+    }
+    fn put(&self, key: &str, bytes: &[u8]) {
+        let _ = self.call("put", &[&key.into(), &js_sys::Uint8Array::from(bytes).into()]);
+    }
+    fn remove(&self, key: &str) {
+        let _ = self.call("delete", &[&key.into()]);
+    }
+    fn keys(&self, done: Box<dyn FnOnce(Vec<String>)>) {
+        let ok = Closure::once(move |v: JsValue| {
+            let keys = js_sys::Array::from(&v).iter().filter_map(|k| k.as_string()).collect();
+            done(keys);
+        });
+        let _ = self.call("keys", &[]).then(&ok);
+        ok.forget();
+    }
+}
+
+// a page camera: anything with pos (xyz) and MVP (4x4) Float32Arrays, e.g. a Camera
+pub struct JsEye(pub JsValue);
+
+impl JsEye {
+    fn read(&self, key: &str, out: &mut [f32]) {
+        if let Some(a) = js_sys::Reflect::get(&self.0, &key.into()).ok().and_then(|a| a.dyn_into::<js_sys::Float32Array>().ok()) {
+            for (k, v) in out.iter_mut().enumerate().take(a.length() as usize) {
+                *v = a.get_index(k as u32);
+            }
+        }
+    }
+}
+
+impl Eye for JsEye {
+    fn pos(&self) -> [f32; 3] {
+        let mut p = [0.0; 3];
+        self.read("pos", &mut p);
+        p
+    }
+    fn mvp(&self) -> [f32; 16] {
+        let mut vp = [0.0; 16];
+        self.read("MVP", &mut vp);
+        vp
+    }
+}
+
+pub type Queue = dyn Scheduler<Task = js_sys::Object, Done = Promise>;
+
+// a page queue set: { schedule(start, priority) => { promise, task }, bump(task, priority),
+// abort(task) }, e.g. createQueues
+pub struct JsQueues(pub JsValue);
+
+impl JsQueues {
+    fn call(&self, name: &str, task: &js_sys::Object, priority: i32) {
+        if let Some(f) = js_sys::Reflect::get(&self.0, &name.into()).ok().and_then(|f| f.dyn_into::<Function>().ok()) {
+            let _ = f.call2(&self.0, task, &JsValue::from_f64(priority as f64));
+        }
+    }
+}
+
+impl Scheduler for JsQueues {
+    type Task = js_sys::Object;
+    type Done = Promise;
+    fn schedule(&self, start: Box<dyn FnMut() -> Promise>, priority: i32) -> Option<(js_sys::Object, Promise)> {
+        let f = js_sys::Reflect::get(&self.0, &"schedule".into()).ok()?.dyn_into::<Function>().ok()?;
+        let start = Closure::wrap(start);
+        let o = f.call2(&self.0, start.as_ref(), &JsValue::from_f64(priority as f64)).ok()?;
+        start.forget();
+        let p = js_sys::Reflect::get(&o, &"promise".into()).ok()?.dyn_into::<Promise>().ok()?;
+        let task = js_sys::Reflect::get(&o, &"task".into()).ok()?.dyn_into::<js_sys::Object>().ok()?;
+        Some((task, p))
+    }
+    fn bump(&self, task: &js_sys::Object, priority: i32) {
+        self.call("bump", task, priority);
+    }
+    fn abort(&self, task: &js_sys::Object) {
+        self.call("abort", task, 0);
+    }
+}
+
+// { grid: Int32Array(CHUNK²), layers: Int32Array(2c), polys: Int32Array(5n), edges: Uint32Array(2m),
+//   links: Int32Array(6k) }; grid is the top y per column, layers every (column, y) cell
+pub fn surface(s: &Surface) -> js_sys::Object {
+    let polys: Vec<i32> = s.polys.iter().flatten().copied().collect();
+    let edges: Vec<u32> = s.edges.iter().flat_map(|e| [e.0, e.1]).collect();
+    let links: Vec<i32> = s.links.iter().flat_map(|l| l.0.into_iter().chain(l.1)).collect();
+    let o = js_sys::Object::new();
+    let _ = js_sys::Reflect::set(&o, &"grid".into(), &js_sys::Int32Array::from(s.grid().as_slice()));
+    let _ = js_sys::Reflect::set(&o, &"layers".into(), &js_sys::Int32Array::from(s.layers().as_slice()));
+    let _ = js_sys::Reflect::set(&o, &"polys".into(), &js_sys::Int32Array::from(polys.as_slice()));
+    let _ = js_sys::Reflect::set(&o, &"edges".into(), &js_sys::Uint32Array::from(edges.as_slice()));
+    let _ = js_sys::Reflect::set(&o, &"links".into(), &js_sys::Int32Array::from(links.as_slice()));
+    o
+}
+
+// promise of the response body as a Uint8Array; rejects on HTTP errors like create_image
+pub fn fetch_bytes(url: &str, signal: &web_sys::AbortSignal) -> Promise {
+    let init = web_sys::RequestInit::new();
+    init.set_signal(Some(signal));
+    let p = web_sys::window().unwrap().fetch_with_str_and_init(url, &init);
+    let url = url.to_string();
+    let p = then(&p, move |v| {
+        let r: web_sys::Response = v.unchecked_into();
+        if !r.ok() {
+            return Err(js_sys::Error::new(&format!("failed to load {} ({})", url, r.status())).into());
+        }
+        r.array_buffer().map(JsValue::from)
+    });
+    then(&p, |v| Ok(js_sys::Uint8Array::new(&v).into()))
+}
+
+// blob: url over bytes, for handing cached files to create_image or a worker
+pub fn object_url(bytes: &[u8]) -> Result<String, JsValue> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts)?;
+    web_sys::Url::create_object_url_with_blob(&blob)
+}
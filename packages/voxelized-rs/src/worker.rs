@@ -0,0 +1,220 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use js_sys::{ Function, Object, Promise, Reflect };
+use web_sys::{
+    AbortController,
+    AbortSignal,
+    Blob,
+    DedicatedWorkerGlobalScope,
+    ImageBitmap,
+    MessageEvent,
+    OffscreenCanvas,
+    OffscreenCanvasRenderingContext2d,
+    RequestInit,
+    Response,
+    Worker,
+};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::bake::{ bake, Baked };
+use crate::host::Pixels;
+use crate::mesher::Mesher;
+use crate::utils as U;
+use crate::web as W;
+use crate::world::World;
+
+// protocol, mirroring voxelized-js's worker.ts:
+//   page -> worker  { id, url, n, tol?, quad? }   download, decode and mesh one region atlas
+//                   { id, abort: true }           drop that job; no reply is sent for it
+//   worker -> page  { id, bitmap, baked }         bitmap and baked buffers are transferred
+//                   { id, error }
+
+fn id_of(o: &JsValue) -> Option<u32> {
+    Reflect::get(o, &"id".into()).ok()?.as_f64().map(|x| x as u32)
+}
+
+fn msg(id: u32) -> Object {
+    let o = Object::new();
+    let _ = Reflect::set(&o, &"id".into(), &JsValue::from_f64(id as f64));
+    o
+}
+
+// scaled to the world's atlas size like the page draws it, so chunk offsets agree for any file size
+fn pixels(bmp: &ImageBitmap, world: &World) -> Result<Pixels, JsValue> {
+    let (w, h) = world.atlas();
+    let canvas = OffscreenCanvas::new(w, h)?;
+    let ctx: OffscreenCanvasRenderingContext2d = canvas
+        .get_context("2d")?
+        .ok_or_else(|| js_sys::Error::new("no 2d context"))?
+        .unchecked_into();
+    ctx.draw_image_with_image_bitmap_and_dw_and_dh(bmp, 0.0, 0.0, w as f64, h as f64)?;
+    let data = ctx.get_image_data(0.0, 0.0, w as f64, h as f64)?.data().to_vec();
+    Ok(Pixels { width: w, data })
+}
+
+fn run(scope: &DedicatedWorkerGlobalScope, jobs: &Rc<RefCell<HashMap<u32, AbortController>>>, id: u32, data: &JsValue) {
+    let url = W::get_str(data, "url").unwrap_or_default();
+    let n = W::get_f32(data, "n", U::CHUNK as f32) as i32;
+    let world = World { region: n * U::CHUNK, ..World::default() }.fixed();
+    let tol = Reflect::get(data, &"tol".into())
+        .ok()
+        .and_then(|v| v.as_f64())
+        .map(|t| t as u8);
+    let quad = Reflect::get(data, &"quad".into()).ok().is_some_and(|v| v.is_truthy());
+    let mesher = if quad { Mesher::Quad } else { tol.map_or(Mesher::Box, Mesher::Color) };
+    let Ok(ctrl) = AbortController::new() else {
+        return;
+    };
+    let signal = ctrl.signal();
+    jobs.borrow_mut().insert(id, ctrl);
+    let init = RequestInit::new();
+    init.set_signal(Some(&signal));
+    let p = scope.fetch_with_str_and_init(&url, &init);
+    let p = W::then(&p, move |v| {
+        let r: Response = v.unchecked_into();
+        if !r.ok() {
+            return Err(js_sys::Error::new(&format!("failed to load {} ({})", url, r.status())).into());
+        }
+        r.blob().map(JsValue::from)
+    });
+    let s = scope.clone();
+    let p = W::then(&p, move |v| {
+        let blob: Blob = v.unchecked_into();
+        s.create_image_bitmap_with_blob(&blob).map(JsValue::from)
+    });
+    let (s, j, sig) = (scope.clone(), jobs.clone(), signal.clone());
+    let p = W::then(&p, move |v| {
+        let bmp: ImageBitmap = v.unchecked_into();
+        let px = pixels(&bmp, &world)?;
+        if sig.aborted() {
+            return Ok(JsValue::UNDEFINED);
+        }
+        let (baked, list) = bake(&px, &world, mesher).to_js();
+        let o = msg(id);
+        let _ = Reflect::set(&o, &"bitmap".into(), &bmp);
+        let _ = Reflect::set(&o, &"baked".into(), &baked);
+        list.push(&bmp);
+        j.borrow_mut().remove(&id);
+        s.post_message_with_transfer(&o, &list)?;
+        Ok(JsValue::UNDEFINED)
+    });
+    let (s, j) = (scope.clone(), jobs.clone());
+    let err = Closure::once(move |e: JsValue| {
+        j.borrow_mut().remove(&id);
+        if signal.aborted() {
+            return;
+        }
+        let o = msg(id);
+        let _ = Reflect::set(&o, &"error".into(), &JsValue::from_str(&W::reason(&e)));
+        let _ = s.post_message(&o);
+    });
+    let _ = p.catch(&err);
+    err.forget();
+}
+
+// call once inside a worker after the wasm module is initialised:
+//   import init, { serveAtlas } from 'voxelized-rs'; await init(); serveAtlas()
+#[wasm_bindgen(js_name = serveAtlas)]
+pub fn serve_atlas() {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let jobs: Rc<RefCell<HashMap<u32, AbortController>>> = Rc::new(RefCell::new(HashMap::new()));
+    let s = scope.clone();
+    let onmessage = Closure::wrap(
+        Box::new(move |e: MessageEvent| {
+            let data = e.data();
+            let Some(id) = id_of(&data) else {
+                return;
+            };
+            if Reflect::get(&data, &"abort".into()).ok().is_some_and(|v| v.is_truthy()) {
+                if let Some(c) = jobs.borrow_mut().remove(&id) {
+                    c.abort();
+                }
+                return;
+            }
+            run(&s, &jobs, id, &data);
+        }) as Box<dyn FnMut(MessageEvent)>
+    );
+    scope.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+}
+
+struct BridgeState {
+    next: u32,
+    waiting: HashMap<u32, (Function, Function)>,
+}
+
+// the page's end of a serveAtlas worker; one job per region download
+#[derive(Clone)]
+pub struct Bridge {
+    worker: Worker,
+    st: Rc<RefCell<BridgeState>>,
+}
+
+impl Bridge {
+    pub fn new(worker: Worker) -> Bridge {
+        let st = Rc::new(RefCell::new(BridgeState { next: 0, waiting: HashMap::new() }));
+        let s = st.clone();
+        let onmessage = Closure::wrap(
+            Box::new(move |e: MessageEvent| {
+                let data = e.data();
+                let Some(id) = id_of(&data) else {
+                    return;
+                };
+                let Some((resolve, reject)) = s.borrow_mut().waiting.remove(&id) else {
+                    return;
+                };
+                if let Some(why) = W::get_str(&data, "error") {
+                    let _ = reject.call1(&JsValue::NULL, &js_sys::Error::new(&why).into());
+                    return;
+                }
+                let _ = resolve.call1(&JsValue::NULL, &data);
+            }) as Box<dyn FnMut(MessageEvent)>
+        );
+        worker.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+        Bridge { worker, st }
+    }
+    // promise of { bitmap, baked }; aborting the signal cancels the job in the worker too
+    pub fn run(&self, url: &str, n: i32, mesher: Mesher, signal: &AbortSignal) -> Promise {
+        let id = {
+            let mut st = self.st.borrow_mut();
+            st.next += 1;
+            st.next
+        };
+        let o = msg(id);
+        let _ = Reflect::set(&o, &"url".into(), &JsValue::from_str(url));
+        let _ = Reflect::set(&o, &"n".into(), &JsValue::from_f64(n as f64));
+        match mesher {
+            Mesher::Color(t) => {
+                let _ = Reflect::set(&o, &"tol".into(), &JsValue::from_f64(t as f64));
+            }
+            Mesher::Quad => {
+                let _ = Reflect::set(&o, &"quad".into(), &JsValue::TRUE);
+            }
+            Mesher::Box => {}
+        }
+        let p = Promise::new(&mut |resolve: Function, reject: Function| {
+            self.st.borrow_mut().waiting.insert(id, (resolve, reject));
+        });
+        let (worker, st) = (self.worker.clone(), self.st.clone());
+        W::on_abort(signal, move || {
+            let Some((_, reject)) = st.borrow_mut().waiting.remove(&id) else {
+                return;
+            };
+            let o = msg(id);
+            let _ = Reflect::set(&o, &"abort".into(), &JsValue::TRUE);
+            let _ = worker.post_message(&o);
+            let _ = reject.call1(&JsValue::NULL, &js_sys::Error::new("aborted").into());
+        });
+        let _ = self.worker.post_message(&o);
+        p
+    }
+}
+
+// what a worker reply carries, split back into the bitmap and the decoded region
+pub fn reply(v: &JsValue) -> Option<(ImageBitmap, Baked)> {
+    let bmp = Reflect::get(v, &"bitmap".into()).ok()?.dyn_into::<ImageBitmap>().ok()?;
+    let baked = Baked::from_js(&Reflect::get(v, &"baked".into()).ok()?)?;
+    Some((bmp, baked))
+}
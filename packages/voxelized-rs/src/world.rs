@@ -0,0 +1,159 @@
+use wasm_bindgen::prelude::*;
+use js_sys::{ Array, Object, Reflect };
+use serde::{ Deserialize, Serialize };
+use crate::utils as U;
+use crate::web as W;
+
+// the tiles a dataset covers and where they sit in world space; defaults to the bundled scope
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct World {
+    pub x0: i32,
+    pub x1: i32,
+    pub y0: i32,
+    pub y1: i32,
+    // voxels per region side; a multiple of CHUNK, at most CHUNK chunks (the atlas layout)
+    pub region: i32,
+    // world position of the corner of region (x0, y1)
+    pub origin: [f32; 3],
+    // map zoom the tiles were cut at
+    pub zoom: i32,
+    // regions searched around the camera each frame, in each direction
+    pub prefetch: i32,
+    // regions this close are fetched even when out of view
+    pub near: i32,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World {
+            x0: 28,
+            x1: 123,
+            y0: 75,
+            y1: 79,
+            region: U::REGION,
+            origin: [0.0, 0.0, 0.0],
+            zoom: 17,
+            prefetch: U::PREFETCH,
+            near: U::SLOT,
+        }
+    }
+}
+
+impl World {
+    pub fn new(x0: i32, x1: i32, y0: i32, y1: i32) -> World {
+        World { x0: x0.min(x1), x1: x0.max(x1), y0: y0.min(y1), y1: y0.max(y1), ..Default::default() }
+    }
+    // { x0, x1, y0, y1, region, origin: [x, y, z], zoom, prefetch, near }; missing keys keep the defaults
+    pub fn from_js(o: &JsValue) -> World {
+        let d = World::default();
+        if !o.is_object() {
+            return d;
+        }
+        let get = |k: &str, v: i32| W::get_f32(o, k, v as f32) as i32;
+        let mut w = World::new(get("x0", d.x0), get("x1", d.x1), get("y0", d.y0), get("y1", d.y1));
+        w.region = get("region", d.region);
+        w.zoom = get("zoom", d.zoom);
+        w.prefetch = get("prefetch", d.prefetch);
+        w.near = get("near", d.near);
+        if let Ok(a) = Reflect::get(o, &"origin".into()) {
+            if let Some(a) = a.dyn_ref::<Array>() {
+                for k in 0..3 {
+                    w.origin[k] = a.get(k as u32).as_f64().unwrap_or(0.0) as f32;
+                }
+            }
+        }
+        w.fixed()
+    }
+    // ordered bounds and a region size the atlas layout can hold
+    pub fn fixed(self) -> World {
+        let region = (self.region / U::CHUNK).clamp(1, U::CHUNK) * U::CHUNK;
+        let mut w = World { region, prefetch: self.prefetch.max(1), near: self.near.max(0), ..self };
+        (w.x0, w.x1) = (self.x0.min(self.x1), self.x0.max(self.x1));
+        (w.y0, w.y1) = (self.y0.min(self.y1), self.y0.max(self.y1));
+        w
+    }
+    pub fn to_js(&self) -> Object {
+        let o = Object::new();
+        let fields = [
+            ("x0", self.x0),
+            ("x1", self.x1),
+            ("y0", self.y0),
+            ("y1", self.y1),
+            ("region", self.region),
+            ("zoom", self.zoom),
+            ("prefetch", self.prefetch),
+            ("near", self.near),
+        ];
+        for (k, v) in fields {
+            let _ = Reflect::set(&o, &k.into(), &JsValue::from_f64(v as f64));
+        }
+        let _ = Reflect::set(&o, &"origin".into(), &js_sys::Float32Array::from(&self.origin[..]));
+        o
+    }
+    pub fn row(&self) -> i32 {
+        self.x1 - self.x0 + 1
+    }
+    pub fn chunks(&self) -> i32 {
+        self.region / U::CHUNK
+    }
+    // top-left pixel of chunk (i, j, k) in the region atlas: a block of chunks per k, 4 blocks across
+    pub fn tile(&self, i: i32, j: i32, k: i32) -> (u32, u32) {
+        let block = self.chunks() * U::TILE;
+        (((k & 3) * block + i * U::TILE) as u32, ((k >> 2) * block + j * U::TILE) as u32)
+    }
+    // pixel size of a whole region atlas, which every decode is scaled to
+    pub fn atlas(&self) -> (u32, u32) {
+        let (n, block) = (self.chunks(), self.chunks() * U::TILE);
+        ((n.min(4) * block) as u32, ((n + 3) / 4 * block) as u32)
+    }
+    // extent along x, in voxels
+    pub fn span(&self) -> f32 {
+        (self.row() * self.region) as f32
+    }
+    pub fn off_of(&self, i: i32, j: i32) -> (f32, f32, f32) {
+        let o = self.origin;
+        (o[0] + ((i - self.x0) * self.region) as f32, o[1], o[2] + ((self.y1 - j) * self.region) as f32)
+    }
+    pub fn pos_of(&self, pos: &[f32; 3]) -> (i32, i32) {
+        let r = self.region as f32;
+        (
+            self.x0 + ((pos[0] - self.origin[0]) / r).floor() as i32,
+            self.y1 - ((pos[2] - self.origin[2]) / r).floor() as i32,
+        )
+    }
+    pub fn region_id(&self, i: i32, j: i32) -> i32 {
+        (i - self.x0) + self.row() * (j - self.y0)
+    }
+    pub fn scoped(&self, i: i32, j: i32) -> bool {
+        self.x0 <= i && i <= self.x1 && self.y0 <= j && j <= self.y1
+    }
+    // a region-local voxel coordinate
+    pub fn inside(&self, lx: i32, ly: i32, lz: i32) -> bool {
+        let n = self.region;
+        lx >= 0 && lx < n && ly >= 0 && ly < n && lz >= 0 && lz < n
+    }
+    // bounding sphere of the region cube at (rx, ry, rz) against the frustum
+    pub fn culling(&self, vp: &[f32; 16], rx: f32, ry: f32, rz: f32) -> bool {
+        let h = self.region as f32 * 0.5;
+        U::vis_sphere(vp, rx + h, ry + h, rz + h, h * 3f32.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_layout_is_the_4096_atlas() {
+        let w = World::default();
+        assert_eq!(w.atlas(), (4096, 4096));
+        for (i, j, k) in [(0, 0, 0), (3, 15, 5), (15, 15, 15)] {
+            assert_eq!(w.tile(i, j, k), (((k & 3) * 1024 + i * 64) as u32, ((k >> 2) * 1024 + j * 64) as u32));
+        }
+        // a region of 6 chunks: two rows of 4 and 2 blocks of 6 tiles
+        let small = World { region: 96, ..w };
+        assert_eq!(small.atlas(), (4 * 384, 2 * 384));
+        assert_eq!(small.tile(5, 1, 5), (384 + 5 * 64, 384 + 64));
+    }
+}
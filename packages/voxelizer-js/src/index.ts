@@ -1,5 +1,6 @@
 import { rays } from './ray'
 import type { Parsed, V3 } from './types'
+export * from './manifest'
 
 type Write = (path: string, data: Uint8Array) => any
 type Config = { DIST: string; CHUNK: number; CHUNK_W: number; CHUNK_H: number; CHUNK_D: number }
@@ -16,6 +17,7 @@ const clamp = (x = 0, a = 0, b = 1) => (x < a ? a : b < x ? b : x)
 const CONFIG: Config = { DIST: '../dist/', CHUNK: 16, CHUNK_W: 16, CHUNK_H: 16, CHUNK_D: 16 }
 const u8 = (n: number) => (n < 0 ? 0 : n > 255 ? 255 : n | 0)
 
+// rasterizes the chunks only; atlases and their manifest.json are written by the caller, see README
 export async function voxel(glb: Parsed, writeFile: Write, config?: Partial<Config>) {
         const c = { ...CONFIG, ...config }
         const width = glb.aabb.max[0] - glb.aabb.min[0]
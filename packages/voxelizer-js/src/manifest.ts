@@ -0,0 +1,37 @@
+type Write = (path: string, data: Uint8Array) => any
+
+export type World = { x0: number; x1: number; y0: number; y1: number; region?: number; origin?: number[]; zoom?: number }
+export type Entry = { i: number; j: number; hash: string; lods: number[] }
+export type Manifest = { version: string; world?: World; lods: number[]; regions: Entry[] }
+
+const MASK = (1n << 64n) - 1n
+
+// FNV-1a 64 of an atlas file, hex; same as voxelized-rs's manifest::hash
+export const hash = (bytes: Uint8Array) => {
+        let h = 0xcbf29ce484222325n
+        for (let i = 0; i < bytes.length; i++) h = ((h ^ BigInt(bytes[i])) * 0x100000001b3n) & MASK
+        return h.toString(16).padStart(16, '0')
+}
+
+// collects the atlases as they are written and emits the manifest.json voxelized-rs reads
+export const manifest = (version: string, world?: World) => {
+        const m: Manifest = { version, world, lods: [], regions: [] }
+        const index = new Map<string, Entry>()
+        // lists (or replaces) a tile cut at lod from the atlas file's bytes
+        const add = (i: number, j: number, lod: number, bytes: Uint8Array) => {
+                const h = hash(bytes)
+                if (!m.lods.includes(lod)) m.lods.push(lod)
+                const e = index.get(i + '.' + j)
+                if (!e) {
+                        const e = { i, j, hash: h, lods: [lod] }
+                        index.set(i + '.' + j, e)
+                        m.regions.push(e)
+                        return
+                }
+                e.hash = h
+                if (!e.lods.includes(lod)) e.lods.push(lod)
+        }
+        const json = () => JSON.stringify(m)
+        const write = (writeFile: Write, path = 'manifest.json') => writeFile(path, new TextEncoder().encode(json()))
+        return { add, json, write, manifest: m }
+}
@@ -0,0 +1,125 @@
+// voxelized-rs on raw WebGL2 with createArraySlots: every region is a layer of one
+// TEXTURE_2D_ARRAY read through `sampler2DArray iAtlas`, offsets come from `iOffset[n]`
+import { createArraySlots, createCamera, createMesh, createQueues, createRegions } from 'voxelized-rs'
+
+const SLOT = 16
+const REGION = 256
+
+const vs = /* glsl */ `#version 300 es
+in vec3 vertex;
+in vec3 normal;
+in vec3 pos;
+in vec3 scl;
+in float aid;
+uniform mat4 iMVP;
+uniform vec3 iOffset[${SLOT}];
+out vec3 vCenter;
+out float vDiffuse;
+flat out int vAid;
+void main() {
+        vec3 local = vertex * scl + pos;
+        vCenter = floor(local - sign(normal) * 0.5);
+        vDiffuse = dot(normalize(vec3(-0.33, 0.77, 0.55)), normal) * 0.5 + 0.5;
+        vAid = int(aid);
+        gl_Position = iMVP * vec4(iOffset[vAid] + local, 1.0);
+}`
+
+// texel of a region-local voxel, in the layout Chunk::fill_with reads (World::tile)
+const fs = /* glsl */ `#version 300 es
+precision highp float;
+precision highp int;
+precision highp sampler2DArray;
+uniform sampler2DArray iAtlas;
+uniform int iBlock;
+in vec3 vCenter;
+in float vDiffuse;
+flat in int vAid;
+out vec4 fragColor;
+void main() {
+        ivec3 p = ivec3(vCenter);
+        ivec3 c = p >> 4;
+        ivec3 l = p & 15;
+        ivec2 uv = ivec2((c.z & 3) * iBlock + c.x * 64 + (l.z & 3) * 16 + l.x, (c.z >> 2) * iBlock + c.y * 64 + (l.z >> 2) * 16 + l.y);
+        fragColor = vec4(texelFetch(iAtlas, ivec3(uv, vAid), 0).rgb * vDiffuse, 1.0);
+}`
+
+// a unit cube as 36 vertices with their face normals
+const cube = () => {
+        const vertex: number[] = []
+        const normal: number[] = []
+        for (let axis = 0; axis < 3; axis++)
+                for (const side of [0, 1]) {
+                        const [u, v] = [(axis + 1) % 3, (axis + 2) % 3]
+                        const quad = side ? [0, 0, 1, 0, 1, 1, 0, 0, 1, 1, 0, 1] : [0, 0, 1, 1, 1, 0, 0, 0, 0, 1, 1, 1]
+                        for (let k = 0; k < 12; k += 2) {
+                                const p = [0, 0, 0]
+                                const n = [0, 0, 0]
+                                p[axis] = side
+                                p[u] = quad[k]
+                                p[v] = quad[k + 1]
+                                n[axis] = side ? 1 : -1
+                                vertex.push(...p)
+                                normal.push(...n)
+                        }
+                }
+        return { vertex, normal }
+}
+
+const el = document.getElementById('canvas') as HTMLCanvasElement
+const gl = el.getContext('webgl2')!
+const shader = (type: number, src: string) => {
+        const s = gl.createShader(type)!
+        gl.shaderSource(s, src)
+        gl.compileShader(s)
+        if (!gl.getShaderParameter(s, gl.COMPILE_STATUS)) console.warn(gl.getShaderInfoLog(s))
+        return s
+}
+const pg = gl.createProgram()!
+gl.attachShader(pg, shader(gl.VERTEX_SHADER, vs))
+gl.attachShader(pg, shader(gl.FRAGMENT_SHADER, fs))
+gl.linkProgram(pg)
+gl.useProgram(pg)
+gl.enable(gl.DEPTH_TEST)
+
+const { vertex, normal } = cube()
+for (const [key, data] of [['vertex', vertex], ['normal', normal]] as const) {
+        const loc = gl.getAttribLocation(pg, key)
+        gl.bindBuffer(gl.ARRAY_BUFFER, gl.createBuffer())
+        gl.bufferData(gl.ARRAY_BUFFER, new Float32Array(data), gl.STATIC_DRAW)
+        gl.enableVertexAttribArray(loc)
+        gl.vertexAttribPointer(loc, 3, gl.FLOAT, false, 0, 0)
+}
+gl.uniform1i(gl.getUniformLocation(pg, 'iBlock'), REGION * 4)
+
+const mesh = createMesh()
+const cam = createCamera({ X: 22912, Y: 400, Z: 20096, yaw: Math.PI / 2, pitch: -Math.PI / 2 + 0.01 })
+const regions = createRegions(mesh, cam, createQueues(4, 2), { region: REGION })
+regions.atlasUrl('https://r2.glre.dev/atlas/v1/{z}_{i}_{j}.{ext}', 'webp')
+// layers live on texture unit 0; begin caps the regions kept visible at SLOT
+const slots = createArraySlots(SLOT, 0)
+
+let loading = false
+let last = 0
+const frame = (now: number) => {
+        requestAnimationFrame(frame)
+        el.width = el.clientWidth * devicePixelRatio
+        el.height = el.clientHeight * devicePixelRatio
+        gl.viewport(0, 0, el.width, el.height)
+        cam.update(el.width / el.height)
+        if (!loading && now - last >= 100) {
+                regions.vis()
+                mesh.reset()
+                slots.begin(regions)
+                loading = true
+                last = now
+        }
+        if (loading && slots.step(gl, pg, 6)) {
+                mesh.commit()
+                loading = false
+        }
+        gl.uniformMatrix4fv(gl.getUniformLocation(pg, 'iMVP'), false, cam.MVP)
+        gl.clear(gl.COLOR_BUFFER_BIT | gl.DEPTH_BUFFER_BIT)
+        const count = mesh.draw(gl, pg)
+        if (count) gl.drawArraysInstanced(gl.TRIANGLES, 0, 36, count)
+}
+requestAnimationFrame(frame)